@@ -0,0 +1,878 @@
+use crate::error::AssemblyError;
+use crate::processor::MAX_ROM_SIZE;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Guards against files that include each other.
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// Guards against constants defined in terms of each other.
+const MAX_CONSTANT_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Str(Vec<u8>),
+    Symbol(char),
+}
+
+/// A token and the 1-based column it starts at.
+#[derive(Clone, Debug)]
+struct Spanned {
+    token: Token,
+    column: usize,
+}
+
+/// The file and line a statement was read from.
+#[derive(Clone, Debug)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, column: usize, message: String) -> AssemblyError {
+        AssemblyError {
+            file: self.file.clone(),
+            line: self.line,
+            column,
+            message,
+        }
+    }
+}
+
+enum Symbol {
+    Label(u16),
+    Constant(Vec<Spanned>, Location),
+}
+
+/// An instruction or data directive, with its operands split at commas.
+struct Statement {
+    location: Location,
+    address: u16,
+    mnemonic: Spanned,
+    operands: Vec<Vec<Spanned>>,
+}
+
+/// An operand once its registers have been recognised.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    V(u16),
+    Range(u16, u16),        // Vx - Vy
+    Register(&'static str), // I, [I], DT, ST, K, F, HF, B or R
+    Long(i64, usize),       // long addr, for XO-CHIP's F000 nnnn.
+    Value(i64, usize),      // An expression and its column.
+}
+
+/// Registers that are written as bare words and so cannot be used as symbol names.
+const REGISTERS: [&str; 8] = ["I", "DT", "ST", "K", "F", "HF", "B", "R"];
+
+/// Assembles source text into a ROM to be loaded at origin, usually the platform's load address. Included files
+/// are found relative to the working directory.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::new(origin);
+    assembler.parse(source, "<input>", Path::new(""), 0)?;
+    assembler.encode()
+}
+
+/// Assembles a file into a ROM to be loaded at origin. Included files are found relative to the file including them.
+pub fn assemble_file(path: &Path, origin: u16) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::new(origin);
+    assembler.include(path, None, 0)?;
+    assembler.encode()
+}
+
+/// Collects statements and symbols in a first pass over the source, then encodes them once every label is known.
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+    statements: Vec<Statement>,
+    origin: usize,
+    address: usize,
+}
+
+impl Assembler {
+    fn new(origin: u16) -> Assembler {
+        Assembler {
+            symbols: HashMap::new(),
+            statements: Vec::new(),
+            origin: origin as usize,
+            address: origin as usize,
+        }
+    }
+
+    /// Reads and parses a file. from is the statement including it, if any.
+    fn include(
+        &mut self,
+        path: &Path,
+        from: Option<(&Location, usize)>,
+        depth: usize,
+    ) -> Result<(), AssemblyError> {
+        let name = path.display().to_string();
+        let fail = |message: String| match from {
+            Some((location, column)) => location.error(column, message),
+            None => AssemblyError {
+                file: name.clone(),
+                line: 0,
+                column: 0,
+                message,
+            },
+        };
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(fail(format!("Includes are nested too deeply at {}.", name)));
+        }
+        let source = fs::read_to_string(path).map_err(|e| fail(format!("{}: {}", name, e)))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        self.parse(&source, &name, directory, depth)
+    }
+
+    fn parse(
+        &mut self,
+        source: &str,
+        file: &str,
+        directory: &Path,
+        depth: usize,
+    ) -> Result<(), AssemblyError> {
+        for (index, text) in source.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: index + 1,
+            };
+            let mut tokens = tokenize(text, &location)?;
+
+            while tokens.len() >= 2 && tokens[1].token == Token::Symbol(':') {
+                let label = tokens.remove(0);
+                tokens.remove(0);
+                match label.token {
+                    Token::Ident(name) => self.define(
+                        &name,
+                        Symbol::Label(self.address as u16),
+                        &location,
+                        label.column,
+                    )?,
+                    _ => {
+                        return Err(
+                            location.error(label.column, "Expected a label name.".to_string())
+                        )
+                    }
+                }
+            }
+            if tokens.is_empty() {
+                continue;
+            }
+
+            if let [Spanned {
+                token: Token::Ident(name),
+                column,
+            }, separator, ..] = tokens.as_slice()
+            {
+                let is_constant = match &separator.token {
+                    Token::Symbol('=') => true,
+                    Token::Ident(word) => word.eq_ignore_ascii_case("equ"),
+                    _ => false,
+                };
+                if is_constant {
+                    let expression = tokens[2..].to_vec();
+                    if expression.is_empty() {
+                        return Err(location.error(separator.column, "Missing value.".to_string()));
+                    }
+                    let symbol = Symbol::Constant(expression, location.clone());
+                    self.define(name, symbol, &location, *column)?;
+                    continue;
+                }
+            }
+
+            let mnemonic = tokens.remove(0);
+            let name = match &mnemonic.token {
+                Token::Ident(name) => name.to_uppercase(),
+                _ => {
+                    return Err(
+                        location.error(mnemonic.column, "Expected an instruction.".to_string())
+                    )
+                }
+            };
+            if name == "INCLUDE" {
+                match tokens.as_slice() {
+                    [Spanned {
+                        token: Token::Str(path),
+                        column,
+                    }] => {
+                        let path =
+                            directory.join(PathBuf::from(String::from_utf8_lossy(path).as_ref()));
+                        self.include(&path, Some((&location, *column)), depth + 1)?;
+                    }
+                    _ => {
+                        return Err(location.error(
+                            mnemonic.column,
+                            "include expects a quoted file name.".to_string(),
+                        ))
+                    }
+                }
+                continue;
+            }
+
+            let operands = split_operands(tokens);
+            let statement = Statement {
+                location,
+                address: self.address as u16,
+                mnemonic,
+                operands,
+            };
+            self.address += statement.size(&name);
+            let max_size = MAX_ROM_SIZE.min(0x10000 - self.origin);
+            if self.address > self.origin + max_size {
+                return Err(statement.location.error(
+                    statement.mnemonic.column,
+                    format!("The program is larger than {} bytes.", max_size),
+                ));
+            }
+            self.statements.push(statement);
+        }
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        name: &str,
+        symbol: Symbol,
+        location: &Location,
+        column: usize,
+    ) -> Result<(), AssemblyError> {
+        if register(name).is_some() || REGISTERS.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+            return Err(location.error(column, format!("'{}' is a register name.", name)));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(location.error(column, format!("'{}' is already defined.", name)));
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    /// The second pass, once every label has an address.
+    fn encode(&self) -> Result<Vec<u8>, AssemblyError> {
+        let mut rom = Vec::new();
+        for statement in &self.statements {
+            let bytes = self.encode_statement(statement)?;
+            let offset = statement.address as usize - self.origin;
+            rom.resize(offset, 0x0);
+            rom.extend(bytes);
+        }
+        Ok(rom)
+    }
+
+    fn encode_statement(&self, statement: &Statement) -> Result<Vec<u8>, AssemblyError> {
+        let location = &statement.location;
+        let name = match &statement.mnemonic.token {
+            Token::Ident(name) => name.to_uppercase(),
+            _ => unreachable!("Statements start with a mnemonic."),
+        };
+
+        match name.as_str() {
+            "DB" => {
+                let mut bytes = Vec::new();
+                for operand in &statement.operands {
+                    match operand.as_slice() {
+                        [Spanned {
+                            token: Token::Str(text),
+                            ..
+                        }] => bytes.extend(text),
+                        _ => {
+                            let (value, column) = self.value(operand, location)?;
+                            bytes
+                                .push(check(value, column, -0x80, 0xFF, "a byte", location)? as u8);
+                        }
+                    }
+                }
+                return Ok(bytes);
+            }
+            "DW" => {
+                let mut bytes = Vec::new();
+                for operand in &statement.operands {
+                    let (value, column) = self.value(operand, location)?;
+                    let word = check(value, column, -0x8000, 0xFFFF, "a word", location)?;
+                    bytes.extend(&word.to_be_bytes());
+                }
+                return Ok(bytes);
+            }
+            _ => (),
+        }
+
+        if statement.operands.iter().any(Vec::is_empty) {
+            return Err(location.error(
+                statement.mnemonic.column,
+                format!("Missing operand for {}.", name),
+            ));
+        }
+        let operands = statement
+            .operands
+            .iter()
+            .map(|operand| self.operand(operand, location))
+            .collect::<Result<Vec<_>, _>>()?;
+        let nibble = |value, column| check(value, column, 0x0, 0xF, "a nibble", location);
+        let byte =
+            |value, column| check(value, column, -0x80, 0xFF, "a byte", location).map(|b| b & 0xFF);
+        let addr = |value, column| check(value, column, 0x0, 0xFFF, "an address", location);
+
+        use Operand::*;
+        let opcode = match (name.as_str(), operands.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("AUDIO", []) => 0xF002,
+            ("SCD", [Value(n, c)]) => 0x00C0 | nibble(*n, *c)?,
+            ("SCU", [Value(n, c)]) => 0x00D0 | nibble(*n, *c)?,
+            ("SYS", [Value(a, c)]) => addr(*a, *c)?,
+            ("JP", [Value(a, c)]) => 0x1000 | addr(*a, *c)?,
+            ("JP", [V(0x0), Value(a, c)]) => 0xB000 | addr(*a, *c)?,
+            ("CALL", [Value(a, c)]) => 0x2000 | addr(*a, *c)?,
+            ("SE", [V(x), Value(k, c)]) => 0x3000 | x << 8 | byte(*k, *c)?,
+            ("SNE", [V(x), Value(k, c)]) => 0x4000 | x << 8 | byte(*k, *c)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+            ("LD", [Register("[I]"), Range(x, y)]) => 0x5002 | x << 8 | y << 4,
+            ("LD", [Range(x, y), Register("[I]")]) => 0x5003 | x << 8 | y << 4,
+            ("LD", [V(x), Value(k, c)]) => 0x6000 | x << 8 | byte(*k, *c)?,
+            ("ADD", [V(x), Value(k, c)]) => 0x7000 | x << 8 | byte(*k, *c)?,
+            ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+            ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+            ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+            ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+            ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+            ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+            // Without a Vy the register shifts itself, whatever the shift_uses_vy quirk.
+            ("SHR", [V(x)]) => 0x8006 | x << 8 | x << 4,
+            ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+            ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+            ("SHL", [V(x)]) => 0x800E | x << 8 | x << 4,
+            ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+            ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+            ("LD", [Register("I"), Value(a, c)]) => 0xA000 | addr(*a, *c)?,
+            ("LD", [Register("I"), Long(a, c)]) => {
+                let long = check(*a, *c, 0x0, 0xFFFF, "an address", location)?;
+                return Ok(vec![0xF0, 0x00, (long >> 8) as u8, long as u8]);
+            }
+            ("RND", [V(x), Value(k, c)]) => 0xC000 | x << 8 | byte(*k, *c)?,
+            ("DRW", [V(x), V(y), Value(n, c)]) => 0xD000 | x << 8 | y << 4 | nibble(*n, *c)?,
+            ("SKP", [V(x)]) => 0xE09E | x << 8,
+            ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+            ("PLANE", [Value(n, c)]) => 0xF001 | nibble(*n, *c)? << 8,
+            ("LD", [V(x), Register("DT")]) => 0xF007 | x << 8,
+            ("LD", [V(x), Register("K")]) => 0xF00A | x << 8,
+            ("LD", [Register("DT"), V(x)]) => 0xF015 | x << 8,
+            ("LD", [Register("ST"), V(x)]) => 0xF018 | x << 8,
+            ("ADD", [Register("I"), V(x)]) => 0xF01E | x << 8,
+            ("LD", [Register("F"), V(x)]) => 0xF029 | x << 8,
+            ("LD", [Register("HF"), V(x)]) => 0xF030 | x << 8,
+            ("LD", [Register("B"), V(x)]) => 0xF033 | x << 8,
+            ("PITCH", [V(x)]) => 0xF03A | x << 8,
+            ("LD", [Register("[I]"), V(x)]) => 0xF055 | x << 8,
+            ("LD", [V(x), Register("[I]")]) => 0xF065 | x << 8,
+            ("LD", [Register("R"), V(x)]) => 0xF075 | x << 8,
+            ("LD", [V(x), Register("R")]) => 0xF085 | x << 8,
+            _ if MNEMONICS.contains(&name.as_str()) => {
+                return Err(location.error(
+                    statement.mnemonic.column,
+                    format!("Invalid operands for {}.", name),
+                ))
+            }
+            _ => {
+                return Err(location.error(
+                    statement.mnemonic.column,
+                    format!("Unknown instruction '{}'.", name),
+                ))
+            }
+        };
+        Ok(vec![(opcode >> 8) as u8, opcode as u8])
+    }
+
+    /// Recognises the registers in an operand, evaluating anything else as an expression.
+    fn operand(&self, tokens: &[Spanned], location: &Location) -> Result<Operand, AssemblyError> {
+        let ident = |spanned: &Spanned| match &spanned.token {
+            Token::Ident(name) => Some(name.to_uppercase()),
+            _ => None,
+        };
+        match tokens {
+            [only] => {
+                if let Some(name) = ident(only) {
+                    if let Some(x) = register(&name) {
+                        return Ok(Operand::V(x));
+                    }
+                    if let Some(name) = REGISTERS.iter().find(|r| **r == name) {
+                        return Ok(Operand::Register(name));
+                    }
+                }
+            }
+            [first, dash, last] if dash.token == Token::Symbol('-') => {
+                let first = ident(first).and_then(|name| register(&name));
+                let last = ident(last).and_then(|name| register(&name));
+                if let (Some(x), Some(y)) = (first, last) {
+                    return Ok(Operand::Range(x, y));
+                }
+            }
+            [open, i, close]
+                if open.token == Token::Symbol('[')
+                    && ident(i).as_deref() == Some("I")
+                    && close.token == Token::Symbol(']') =>
+            {
+                return Ok(Operand::Register("[I]"));
+            }
+            [first, rest @ ..] if ident(first).as_deref() == Some("LONG") => {
+                let (value, column) = self.value(rest, location)?;
+                return Ok(Operand::Long(value, column));
+            }
+            _ => (),
+        }
+        let (value, column) = self.value(tokens, location)?;
+        Ok(Operand::Value(value, column))
+    }
+
+    /// Evaluates an expression, returning its value and column.
+    fn value(
+        &self,
+        tokens: &[Spanned],
+        location: &Location,
+    ) -> Result<(i64, usize), AssemblyError> {
+        match tokens.first() {
+            Some(first) => Ok((self.evaluate(tokens, location, 0)?, first.column)),
+            None => Err(location.error(1, "Missing operand.".to_string())),
+        }
+    }
+
+    /// Evaluates terms joined by + and -. A term is a number or a symbol.
+    fn evaluate(
+        &self,
+        tokens: &[Spanned],
+        location: &Location,
+        depth: usize,
+    ) -> Result<i64, AssemblyError> {
+        let mut total = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+        for spanned in tokens {
+            let column = spanned.column;
+            let overflow = || location.error(column, "Expression overflows.".to_string());
+            match (&spanned.token, expect_term) {
+                (Token::Symbol('-'), true) => sign = -sign,
+                (Token::Symbol('+'), true) => (),
+                (Token::Number(number), true) => {
+                    total = add_term(total, sign, *number).ok_or_else(overflow)?;
+                    expect_term = false;
+                }
+                (Token::Ident(name), true) => {
+                    let value = self.symbol(name, location, column, depth)?;
+                    total = add_term(total, sign, value).ok_or_else(overflow)?;
+                    expect_term = false;
+                }
+                (Token::Symbol('+'), false) => {
+                    sign = 1;
+                    expect_term = true;
+                }
+                (Token::Symbol('-'), false) => {
+                    sign = -1;
+                    expect_term = true;
+                }
+                _ => return Err(location.error(column, "Invalid expression.".to_string())),
+            }
+        }
+        if expect_term {
+            let column = tokens.last().map_or(0, |spanned| spanned.column);
+            return Err(location.error(column, "Incomplete expression.".to_string()));
+        }
+        Ok(total)
+    }
+
+    fn symbol(
+        &self,
+        name: &str,
+        location: &Location,
+        column: usize,
+        depth: usize,
+    ) -> Result<i64, AssemblyError> {
+        match self.symbols.get(name) {
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(tokens, defined_at)) => {
+                if depth >= MAX_CONSTANT_DEPTH {
+                    return Err(location
+                        .error(column, format!("'{}' is defined in terms of itself.", name)));
+                }
+                self.evaluate(tokens, defined_at, depth + 1)
+            }
+            None => Err(location.error(column, format!("Undefined symbol '{}'.", name))),
+        }
+    }
+}
+
+/// Adds or subtracts a term, unless the result does not fit.
+fn add_term(total: i64, sign: i64, term: i64) -> Option<i64> {
+    total.checked_add(sign.checked_mul(term)?)
+}
+
+impl Statement {
+    /// The number of bytes the statement assembles to, which must be known before labels have values.
+    fn size(&self, name: &str) -> usize {
+        let is_long = |operand: &Vec<Spanned>| match operand.first() {
+            Some(Spanned {
+                token: Token::Ident(word),
+                ..
+            }) => word.eq_ignore_ascii_case("long"),
+            _ => false,
+        };
+        match name {
+            "DB" => self
+                .operands
+                .iter()
+                .map(|operand| match operand.as_slice() {
+                    [Spanned {
+                        token: Token::Str(text),
+                        ..
+                    }] => text.len(),
+                    _ => 1,
+                })
+                .sum(),
+            "DW" => self.operands.len() * 2,
+            "LD" if self.operands.len() == 2 && is_long(&self.operands[1]) => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// Every instruction name, to tell a misspelt mnemonic from misused operands.
+const MNEMONICS: [&str; 29] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "SYS", "JP", "CALL",
+    "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP",
+    "SKNP", "PLANE",
+];
+
+/// V0 to VF, given an upper case name.
+fn register(name: &str) -> Option<u16> {
+    let name = name.to_uppercase();
+    let digit = name.strip_prefix('V')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u16::from_str_radix(digit, 16).ok()
+}
+
+fn check(
+    value: i64,
+    column: usize,
+    min: i64,
+    max: i64,
+    kind: &str,
+    location: &Location,
+) -> Result<u16, AssemblyError> {
+    if value < min || value > max {
+        return Err(location.error(column, format!("{} does not fit in {}.", value, kind)));
+    }
+    Ok(value as u16)
+}
+
+fn split_operands(tokens: Vec<Spanned>) -> Vec<Vec<Spanned>> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    let mut operands = vec![Vec::new()];
+    for spanned in tokens {
+        if spanned.token == Token::Symbol(',') {
+            operands.push(Vec::new());
+        } else {
+            operands.last_mut().unwrap().push(spanned);
+        }
+    }
+    operands
+}
+
+/// Splits a line into tokens, stopping at a ; comment.
+/// Numbers are decimal, or hex with 0x, # or $, or binary with 0b or %.
+fn tokenize(line: &str, location: &Location) -> Result<Vec<Spanned>, AssemblyError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < chars.len() {
+        let c = chars[position];
+        let column = position + 1;
+        let start = position;
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            position += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            while position < chars.len()
+                && (chars[position].is_ascii_alphanumeric() || "_.".contains(chars[position]))
+            {
+                position += 1;
+            }
+            Token::Ident(chars[start..position].iter().collect())
+        } else if c.is_ascii_digit()
+            || ((c == '#' || c == '$' || c == '%') && position + 1 < chars.len())
+        {
+            position += 1;
+            while position < chars.len()
+                && (chars[position].is_ascii_alphanumeric() || chars[position] == '_')
+            {
+                position += 1;
+            }
+            let text: String = chars[start..position]
+                .iter()
+                .filter(|c| **c != '_')
+                .collect();
+            let lower = text.to_lowercase();
+            let (digits, radix) = if let Some(hex) = lower.strip_prefix("0x") {
+                (hex, 16)
+            } else if let Some(binary) = lower.strip_prefix("0b") {
+                (binary, 2)
+            } else if let Some(hex) = lower.strip_prefix('#').or_else(|| lower.strip_prefix('$')) {
+                (hex, 16)
+            } else if let Some(binary) = lower.strip_prefix('%') {
+                (binary, 2)
+            } else {
+                (lower.as_str(), 10)
+            };
+            let number = i64::from_str_radix(digits, radix)
+                .map_err(|_| location.error(column, format!("Invalid number '{}'.", text)))?;
+            Token::Number(number)
+        } else if c == '"' {
+            position += 1;
+            let mut text = Vec::new();
+            loop {
+                match chars.get(position) {
+                    None => return Err(location.error(column, "Unterminated string.".to_string())),
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = match chars.get(position + 1) {
+                            Some('n') => '\n',
+                            Some('0') => '\0',
+                            Some(&other) => other,
+                            None => {
+                                return Err(
+                                    location.error(column, "Unterminated string.".to_string())
+                                )
+                            }
+                        };
+                        text.extend(escaped.to_string().bytes());
+                        position += 2;
+                    }
+                    Some(other) => {
+                        text.extend(other.to_string().bytes());
+                        position += 1;
+                    }
+                }
+            }
+            position += 1;
+            Token::Str(text)
+        } else if ",:[]-+=".contains(c) {
+            position += 1;
+            Token::Symbol(c)
+        } else {
+            return Err(location.error(column, format!("Unexpected character '{}'.", c)));
+        };
+        tokens.push(Spanned { token, column });
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+    use crate::platform::Platform;
+
+    fn assembled(source: &str) -> Vec<u8> {
+        assemble(source, 0x200).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source, 0x200).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn every_mnemonic_is_encoded() {
+        let cases: [(&str, &[u8]); 50] = [
+            ("CLS", &[0x00, 0xE0]),
+            ("RET", &[0x00, 0xEE]),
+            ("SCR", &[0x00, 0xFB]),
+            ("SCL", &[0x00, 0xFC]),
+            ("EXIT", &[0x00, 0xFD]),
+            ("LOW", &[0x00, 0xFE]),
+            ("HIGH", &[0x00, 0xFF]),
+            ("AUDIO", &[0xF0, 0x02]),
+            ("SCD 4", &[0x00, 0xC4]),
+            ("SCU 15", &[0x00, 0xDF]),
+            ("SYS 0x123", &[0x01, 0x23]),
+            ("JP 0x345", &[0x13, 0x45]),
+            ("JP V0, 0x345", &[0xB3, 0x45]),
+            ("CALL 0xABC", &[0x2A, 0xBC]),
+            ("SE V1, 0x22", &[0x31, 0x22]),
+            ("SNE VA, 255", &[0x4A, 0xFF]),
+            ("SE V1, V2", &[0x51, 0x20]),
+            ("LD [I], V1 - V4", &[0x51, 0x42]),
+            ("LD V4 - V1, [I]", &[0x54, 0x13]),
+            ("LD V5, 0x66", &[0x65, 0x66]),
+            ("ADD V5, -1", &[0x75, 0xFF]),
+            ("LD V1, V2", &[0x81, 0x20]),
+            ("OR V1, V2", &[0x81, 0x21]),
+            ("AND V1, V2", &[0x81, 0x22]),
+            ("XOR V1, V2", &[0x81, 0x23]),
+            ("ADD V1, V2", &[0x81, 0x24]),
+            ("SUB V1, V2", &[0x81, 0x25]),
+            ("SHR V1", &[0x81, 0x16]),
+            ("SHR V1, V2", &[0x81, 0x26]),
+            ("SUBN V1, V2", &[0x81, 0x27]),
+            ("SHL V1", &[0x81, 0x1E]),
+            ("SHL V1, V2", &[0x81, 0x2E]),
+            ("SNE V1, V2", &[0x91, 0x20]),
+            ("LD I, 0xFFF", &[0xAF, 0xFF]),
+            ("LD I, long 0xBEEF", &[0xF0, 0x00, 0xBE, 0xEF]),
+            ("RND VF, 0x0F", &[0xCF, 0x0F]),
+            ("DRW V1, V2, 0", &[0xD1, 0x20]),
+            ("SKP V3", &[0xE3, 0x9E]),
+            ("SKNP V3", &[0xE3, 0xA1]),
+            ("PLANE 3", &[0xF3, 0x01]),
+            ("LD V7, DT", &[0xF7, 0x07]),
+            ("LD V7, K", &[0xF7, 0x0A]),
+            ("LD DT, V7", &[0xF7, 0x15]),
+            ("LD ST, V7", &[0xF7, 0x18]),
+            ("ADD I, V7", &[0xF7, 0x1E]),
+            ("LD F, V7", &[0xF7, 0x29]),
+            ("LD HF, V7", &[0xF7, 0x30]),
+            ("LD B, V7", &[0xF7, 0x33]),
+            ("PITCH V7", &[0xF7, 0x3A]),
+            ("LD [I], V7", &[0xF7, 0x55]),
+        ];
+        for (source, bytes) in cases.iter() {
+            assert_eq!(assembled(source), *bytes, "{}", source);
+        }
+        assert_eq!(assembled("ld v7, [i]"), [0xF7, 0x65]);
+        assert_eq!(assembled("LD R, V7"), [0xF7, 0x75]);
+        assert_eq!(assembled("LD V7, R"), [0xF7, 0x85]);
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let source = "start: JP end\n    LD I, sprite\nsprite: db 0xFF\nend: JP start";
+        assert_eq!(
+            assembled(source),
+            [0x12, 0x05, 0xA2, 0x04, 0xFF, 0x12, 0x00]
+        );
+        assert_eq!(assembled("one: two: JP two"), [0x12, 0x00]);
+    }
+
+    #[test]
+    fn data_directives_lay_out_bytes_and_words() {
+        assert_eq!(
+            assembled(r#"db 1, 0x02, $03, #04, %101, 0b11, -1, "hi\n""#),
+            [1, 2, 3, 4, 5, 3, 0xFF, b'h', b'i', b'\n']
+        );
+        assert_eq!(assembled("dw 0x1234, -2"), [0x12, 0x34, 0xFF, 0xFE]);
+        // Labels after data account for its size.
+        assert_eq!(
+            assembled("db \"abc\"\ndw 0\nhere: JP here"),
+            [b'a', b'b', b'c', 0x0, 0x0, 0x12, 0x05]
+        );
+    }
+
+    #[test]
+    fn constants_are_evaluated_where_they_are_used() {
+        let source = "X = 1\nSPEED equ X + 2\nLD V0, SPEED - 1 + 0x10\nLD I, end - 2\nend:";
+        assert_eq!(assembled(source), [0x60, 0x12, 0xA2, 0x02]);
+        assert_eq!(
+            error("X = Y\nY = X\nLD V0, X"),
+            "<input>:2:5: 'X' is defined in terms of itself."
+        );
+    }
+
+    #[test]
+    fn operands_out_of_range_are_errors() {
+        assert_eq!(
+            error("LD V0, 256"),
+            "<input>:1:8: 256 does not fit in a byte."
+        );
+        assert_eq!(
+            error("JP 0x1000"),
+            "<input>:1:4: 4096 does not fit in an address."
+        );
+        assert_eq!(
+            error("DRW V0, V1, 16"),
+            "<input>:1:13: 16 does not fit in a nibble."
+        );
+        assert_eq!(
+            error("dw 0x10000"),
+            "<input>:1:4: 65536 does not fit in a word."
+        );
+        assert_eq!(
+            error("db -129"),
+            "<input>:1:4: -129 does not fit in a byte."
+        );
+        assert_eq!(
+            error("db 9223372036854775807 + 1"),
+            "<input>:1:26: Expression overflows."
+        );
+        assert_eq!(
+            error("BIG = 9223372036854775807\ndb 0 - BIG - BIG"),
+            "<input>:2:14: Expression overflows."
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line_and_column() {
+        assert_eq!(
+            error("CLS\n  JP nowhere"),
+            "<input>:2:6: Undefined symbol 'nowhere'."
+        );
+        assert_eq!(
+            error("\n\n   FOO V1"),
+            "<input>:3:4: Unknown instruction 'FOO'."
+        );
+        assert_eq!(error("CLS V1"), "<input>:1:1: Invalid operands for CLS.");
+        assert_eq!(
+            error("x: CLS\nx: CLS"),
+            "<input>:2:1: 'x' is already defined."
+        );
+        assert_eq!(error("V1: CLS"), "<input>:1:1: 'V1' is a register name.");
+        assert_eq!(error("LD V0, 1 +"), "<input>:1:10: Incomplete expression.");
+        assert_eq!(error("db \"open"), "<input>:1:4: Unterminated string.");
+        assert_eq!(error("LD V0, 0x"), "<input>:1:8: Invalid number '0x'.");
+    }
+
+    #[test]
+    fn programs_can_be_assembled_for_other_load_addresses() {
+        assert_eq!(assemble("here: JP here", 0x600), Ok(vec![0x16, 0x00]));
+    }
+
+    #[test]
+    fn disassembled_instructions_assemble_back_to_the_same_opcode() {
+        for opcode in 0..=0xFFFF {
+            let long = if opcode == 0xF000 { Some(0x1234) } else { None };
+            if let Some(text) = disasm::decode(opcode, long) {
+                let mut expected = opcode.to_be_bytes().to_vec();
+                expected.extend(long.map_or(vec![], |long| long.to_be_bytes().to_vec()));
+                assert_eq!(assembled(&text), expected, "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn assembled_programs_disassemble_to_their_source() {
+        let source = "start: LD I, sprite\n    DRW V0, V1, 1\n    SE V0, 0x00\n    JP start\n    EXIT\nsprite: db 0x3C";
+        let rom = assembled(source);
+        let listing = disasm::disassemble(&rom, &Platform::default());
+        let text: Vec<&str> = listing.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            text,
+            [
+                "LD I, D20A",
+                "DRW V0, V1, 1",
+                "SE V0, 0x00",
+                "JP L200",
+                "EXIT",
+                "db 0x3C"
+            ]
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use crate::frontend::Audio;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufWriter, Error, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// What the processor wants the speaker to play.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sound {
+    pub playing: bool,             // The sound timer is non-zero.
+    pub pattern: Option<[u8; 16]>, // XO-CHIP audio pattern, played instead of the tone when loaded.
+    pub pitch: u8,                 // XO-CHIP playback rate of the pattern.
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+
+    /// The amplitude, from -1 to 1, at a phase from 0 to 1 through one period.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+        }
+    }
+}
+
+/// The tone played while the sound timer is non-zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioSettings {
+    pub frequency: f32, // Hz.
+    pub volume: f32,    // 0 to 1.
+    pub waveform: Waveform,
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// Turns a Sound into samples.
+pub struct ToneGenerator {
+    settings: AudioSettings,
+    sample_rate: f32,
+    phase: f32, // Position within the current period, or bit index into the XO-CHIP pattern.
+}
+
+impl ToneGenerator {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> ToneGenerator {
+        ToneGenerator {
+            settings,
+            sample_rate: sample_rate as f32,
+            phase: 0.0,
+        }
+    }
+
+    pub fn next_sample(&mut self, sound: &Sound) -> f32 {
+        if !sound.playing {
+            self.phase = 0.0;
+            return 0.0;
+        }
+        let volume = self.settings.volume;
+        match sound.pattern {
+            Some(pattern) => {
+                let bit = self.phase as usize % 128;
+                let level = (pattern[bit / 8] >> (7 - bit % 8)) & 0x1;
+                let rate = 4000.0 * 2f32.powf((sound.pitch as f32 - 64.0) / 48.0);
+                self.phase = (self.phase + rate / self.sample_rate) % 128.0;
+                if level == 0x1 {
+                    volume
+                } else {
+                    -volume
+                }
+            }
+            None => {
+                let sample = self.settings.waveform.sample(self.phase);
+                self.phase = (self.phase + self.settings.frequency / self.sample_rate) % 1.0;
+                sample * volume
+            }
+        }
+    }
+}
+
+/// Discards all sound.
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn play(&mut self, _sound: Sound, _duration: Duration) {}
+}
+
+/// Writes the sound to a 16-bit mono PCM WAV file. Should writing fail, the error is reported once and the rest of
+/// the sound is dropped rather than stopping the emulator.
+pub struct WavAudio<W: Write + Seek = BufWriter<File>> {
+    writer: W,
+    generator: ToneGenerator,
+    samples_written: u32,
+    pending_samples: f64, // Fraction of a sample carried over between calls to play.
+    failed: bool,         // A write failed and has been reported.
+}
+
+impl WavAudio {
+    pub fn create<P: AsRef<Path>>(path: P, settings: AudioSettings) -> Result<WavAudio, Error> {
+        WavAudio::new(BufWriter::new(File::create(path)?), settings)
+    }
+}
+
+impl<W: Write + Seek> WavAudio<W> {
+    pub fn new(writer: W, settings: AudioSettings) -> Result<WavAudio<W>, Error> {
+        let mut wav = WavAudio {
+            writer,
+            generator: ToneGenerator::new(settings, SAMPLE_RATE),
+            samples_written: 0,
+            pending_samples: 0.0,
+            failed: false,
+        };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        let data_size = self.samples_written * 2;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(b"RIFF")?;
+        self.writer.write_all(&(36 + data_size).to_le_bytes())?;
+        self.writer.write_all(b"WAVEfmt ")?;
+        self.writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size.
+        self.writer.write_all(&1u16.to_le_bytes())?; // PCM.
+        self.writer.write_all(&1u16.to_le_bytes())?; // Mono.
+        self.writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        self.writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // Bytes per second.
+        self.writer.write_all(&2u16.to_le_bytes())?; // Bytes per sample.
+        self.writer.write_all(&16u16.to_le_bytes())?; // Bits per sample.
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Audio for WavAudio<W> {
+    fn play(&mut self, sound: Sound, duration: Duration) {
+        self.pending_samples += duration.as_secs_f64() * SAMPLE_RATE as f64;
+        while self.pending_samples >= 1.0 {
+            self.pending_samples -= 1.0;
+            if self.failed {
+                continue;
+            }
+            let sample = self.generator.next_sample(&sound);
+            let sample = (sample * i16::MAX as f32) as i16;
+            match self.writer.write_all(&sample.to_le_bytes()) {
+                Ok(()) => self.samples_written += 1,
+                Err(err) => {
+                    eprintln!("Unable to write audio, the rest will be dropped: {}", err);
+                    self.failed = true;
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavAudio<W> {
+    fn drop(&mut self) {
+        // The header's sizes can only be filled in once all samples are known.
+        if let Err(err) = self.write_header().and_then(|_| self.writer.flush()) {
+            eprintln!("Unable to finish audio file: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PLAYING: Sound = Sound {
+        playing: true,
+        pattern: None,
+        pitch: 64,
+    };
+
+    /// A 1 Hz tone at 64 samples a second, so the phase steps are exact.
+    fn tone(waveform: Waveform) -> ToneGenerator {
+        let settings = AudioSettings {
+            frequency: 1.0,
+            volume: 0.5,
+            waveform,
+        };
+        ToneGenerator::new(settings, 64)
+    }
+
+    #[test]
+    fn tones_have_the_frequency_and_volume() {
+        let mut generator = tone(Waveform::Square);
+        let samples: Vec<f32> = (0..128).map(|_| generator.next_sample(&PLAYING)).collect();
+        assert!(samples[..32].iter().all(|&sample| sample == 0.5));
+        assert!(samples[32..64].iter().all(|&sample| sample == -0.5));
+        assert_eq!(samples[..64], samples[64..]);
+
+        let mut generator = tone(Waveform::Sine);
+        let samples: Vec<f32> = (0..64).map(|_| generator.next_sample(&PLAYING)).collect();
+        let peak = samples
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - 0.5).abs() < 0.001);
+        assert_eq!((samples[16], samples[48]), (0.5, -0.5));
+    }
+
+    #[test]
+    fn patterns_are_played_a_bit_at_a_time() {
+        let mut generator = tone(Waveform::Square);
+        let sound = Sound {
+            pattern: Some([0xff; 16]),
+            ..PLAYING
+        };
+        assert!((0..1000).all(|_| generator.next_sample(&sound) == 0.5));
+    }
+
+    #[test]
+    fn silence_when_the_sound_timer_is_zero() {
+        let mut generator = tone(Waveform::Sawtooth);
+        let silent = Sound {
+            playing: false,
+            ..PLAYING
+        };
+        for _ in 0..30 {
+            generator.next_sample(&PLAYING);
+        }
+        assert!((0..100).all(|_| generator.next_sample(&silent) == 0.0));
+        // The tone starts again from the beginning of a period.
+        assert_eq!(generator.next_sample(&PLAYING), -0.5);
+    }
+
+    fn u32_at(wav: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([wav[at], wav[at + 1], wav[at + 2], wav[at + 3]])
+    }
+
+    #[test]
+    fn wav_files_have_a_sample_per_44100th_of_a_second() {
+        let mut wav = Cursor::new(Vec::new());
+        {
+            let mut audio = WavAudio::new(&mut wav, AudioSettings::default()).unwrap();
+            audio.play(PLAYING, Duration::from_millis(20));
+            assert_eq!(audio.samples_written, 882);
+            // Fractions of a sample are carried over to the next frame.
+            for _ in 0..59 {
+                audio.play(PLAYING, Duration::from_secs(1) / 60);
+            }
+            assert!((43364..=43365).contains(&(audio.samples_written - 882)));
+        }
+        let wav = wav.into_inner();
+        let data_size = wav.len() as u32 - 44;
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4), 36 + data_size);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), data_size);
+        assert_eq!(u32_at(&wav, 24), SAMPLE_RATE);
+    }
+
+    /// Accepts the header, then fails every write, like a full disk.
+    struct Full {
+        header: Cursor<Vec<u8>>,
+    }
+
+    impl Write for Full {
+        fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+            if self.header.position() < 44 {
+                return self.header.write(data);
+            }
+            Err(Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Full {
+        fn seek(&mut self, position: SeekFrom) -> Result<u64, Error> {
+            self.header.seek(position)
+        }
+    }
+
+    #[test]
+    fn write_errors_do_not_stop_the_emulator() {
+        let full = Full {
+            header: Cursor::new(Vec::new()),
+        };
+        let mut audio = WavAudio::new(full, AudioSettings::default()).unwrap();
+        audio.play(PLAYING, Duration::from_millis(20));
+        audio.play(PLAYING, Duration::from_millis(20));
+        assert!(audio.failed);
+        assert_eq!(audio.samples_written, 0);
+    }
+}
@@ -1,12 +1,303 @@
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::Error;
-
-pub fn load_from_file(filename: &str) -> Result<[u8; 3584], Error> {
-    let file = File::open(filename)?;
-    let mut reader = BufReader::new(file);
-    let mut buffer = [0x0; 3584];
-    reader.read(&mut buffer)?;
-    Ok(buffer)
+use crate::error::CartridgeError;
+use crate::octo;
+use crate::platform::Platform;
+use crate::processor::MAX_ROM_SIZE;
+use gif::{ColorOutput, DecodeOptions};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The most a program for the 4 KB machines can use: everything above the interpreter's 0x200 bytes.
+const SMALL_ROM_SIZE: usize = 0x1000 - 0x200;
+
+/// The kinds of file a program can be loaded from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Chip8,         // A raw .ch8 program, also assumed for unknown extensions.
+    SuperChip,     // A raw .sc8 program.
+    XoChip,        // A raw .xo8 program.
+    OctoSource,    // .8o source, compiled when loaded.
+    OctoCartridge, // A GIF with Octo source hidden in its pixels.
+}
+
+impl Format {
+    /// Goes by the file's extension, or failing that its contents.
+    pub fn detect(path: &Path, data: &[u8]) -> Format {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("ch8") => Format::Chip8,
+            Some("sc8") => Format::SuperChip,
+            Some("xo8") => Format::XoChip,
+            Some("8o") => Format::OctoSource,
+            _ if is_gif(data) => Format::OctoCartridge,
+            _ => Format::Chip8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Chip8 => "CHIP-8",
+            Format::SuperChip => "SUPER-CHIP",
+            Format::XoChip => "XO-CHIP",
+            Format::OctoSource => "Octo source",
+            Format::OctoCartridge => "Octo cartridge",
+        }
+    }
+
+    /// The largest program the format's platform has room for. CHIP-8 and SUPER-CHIP ran on machines with 4 KB,
+    /// XO-CHIP and Octo programs can use 64 KB.
+    pub fn max_rom_size(self) -> usize {
+        match self {
+            Format::Chip8 | Format::SuperChip => SMALL_ROM_SIZE,
+            Format::XoChip | Format::OctoSource | Format::OctoCartridge => MAX_ROM_SIZE,
+        }
+    }
+
+    /// The machine programs in the format were written for, used unless the database or command line says otherwise.
+    pub fn platform(self) -> Platform {
+        match self {
+            Format::Chip8 => Platform::COSMAC_VIP,
+            Format::SuperChip => Platform::HP_48,
+            Format::XoChip | Format::OctoSource | Format::OctoCartridge => Platform::XO_CHIP,
+        }
+    }
+}
+
+/// A program ready to be loaded into memory, with what is known about where it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    format: Format,
+    sha1: [u8; 20], // Of the ROM, so compiled programs match their raw equivalents.
+    labels: BTreeMap<String, u16>, // Octo's labels, for the debugger.
+}
+
+impl Cartridge {
+    /// Reads a ROM, Octo source or Octo cartridge. Sources are compiled to be loaded at load_address.
+    pub fn load(path: &Path, load_address: u16) -> Result<Cartridge, CartridgeError> {
+        let data = fs::read(path).map_err(|e| CartridgeError::Io(e.to_string()))?;
+        let format = Format::detect(path, &data);
+        let program = match format {
+            Format::OctoSource => octo::compile_file(path, load_address)?,
+            Format::OctoCartridge => {
+                let source = octo_cartridge_source(&data)?;
+                octo::compile(&source, load_address).map_err(|mut e| {
+                    e.file = path.display().to_string();
+                    e
+                })?
+            }
+            _ => {
+                return Cartridge::new(data, format, BTreeMap::new());
+            }
+        };
+        Cartridge::new(program.rom, format, program.labels)
+    }
+
+    /// Checks a ROM fits in memory.
+    pub fn new(
+        rom: Vec<u8>,
+        format: Format,
+        labels: BTreeMap<String, u16>,
+    ) -> Result<Cartridge, CartridgeError> {
+        if rom.is_empty() {
+            return Err(CartridgeError::Empty);
+        }
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(CartridgeError::TooLarge {
+                size: rom.len(),
+                max: MAX_ROM_SIZE,
+            });
+        }
+        Ok(Cartridge {
+            sha1: Sha1::digest(&rom).into(),
+            rom,
+            format,
+            labels,
+        })
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn size(&self) -> usize {
+        self.rom.len()
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn sha1(&self) -> [u8; 20] {
+        self.sha1
+    }
+
+    /// The SHA-1 in lower case hex, as databases of ROMs list it.
+    pub fn sha1_hex(&self) -> String {
+        self.sha1
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn labels(&self) -> &BTreeMap<String, u16> {
+        &self.labels
+    }
+
+    /// Whether the ROM fits on the platform its format is for. Larger ones still run on a platform with 64 KB,
+    /// but would not on the real thing.
+    pub fn fits_platform(&self) -> bool {
+        self.size() <= self.format.max_rom_size()
+    }
+}
+
+/// Whether data starts like a GIF image.
+fn is_gif(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+/// The colour indices of every frame of a GIF, one after the other, each frame row by row from the top.
+fn gif_indices(data: &[u8]) -> Result<Vec<u8>, gif::DecodingError> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::Indexed);
+    let mut decoder = options.read_info(data)?;
+    let mut indices = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        indices.extend_from_slice(&frame.buffer);
+    }
+    Ok(indices)
+}
+
+/// Digs the Octo source out of a cartridge. Octo keeps a JSON payload in the low two bits of each pixel's colour
+/// index, four pixels to a byte with the highest bits first. The payload starts with its length as a 32-bit big
+/// endian number and holds the source as "program", alongside Octo's settings, which are not used.
+fn octo_cartridge_source(data: &[u8]) -> Result<String, CartridgeError> {
+    let indices = gif_indices(data).map_err(|e| CartridgeError::BadCartridge(e.to_string()))?;
+    let bytes: Vec<u8> = indices
+        .chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 2 | pixel & 0x3))
+        .collect();
+    let no_payload = || CartridgeError::BadCartridge("no Octo program in the image".to_string());
+    let len = match bytes.get(..4) {
+        Some(&[a, b, c, d]) => u32::from_be_bytes([a, b, c, d]) as usize,
+        _ => return Err(no_payload()),
+    };
+    let payload = bytes.get(4..4 + len).ok_or_else(no_payload)?;
+    let payload = String::from_utf8(payload.to_vec()).map_err(|_| no_payload())?;
+    let payload: Value = serde_json::from_str(&payload).map_err(|_| no_payload())?;
+    payload
+        .get("program")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(no_payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gif::{Encoder, Frame};
+
+    /// Hides a payload the way Octo does, in an image 32 pixels wide.
+    fn octo_cartridge(json: &str) -> Vec<u8> {
+        let mut bytes = (json.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(json.as_bytes());
+        let mut pixels: Vec<u8> = bytes
+            .iter()
+            .flat_map(|byte| (0..4).rev().map(move |i| 0xc | (byte >> (i * 2) & 0x3)))
+            .collect();
+        pixels.resize(pixels.len().div_ceil(32) * 32, 0x0);
+        let height = (pixels.len() / 32) as u16;
+        let palette: Vec<u8> = (0..16).flat_map(|i| [i * 16; 3]).collect();
+        let mut gif = Vec::new();
+        let mut encoder = Encoder::new(&mut gif, 32, height, &palette).unwrap();
+        encoder
+            .write_frame(&Frame::from_indexed_pixels(32, height, pixels, None))
+            .unwrap();
+        drop(encoder);
+        gif
+    }
+
+    #[test]
+    fn formats_are_detected() {
+        let gif = octo_cartridge("{}");
+        assert_eq!(Format::detect(Path::new("a.ch8"), &[0x0]), Format::Chip8);
+        assert_eq!(
+            Format::detect(Path::new("a.SC8"), &[0x0]),
+            Format::SuperChip
+        );
+        assert_eq!(Format::detect(Path::new("a.xo8"), &[0x0]), Format::XoChip);
+        assert_eq!(
+            Format::detect(Path::new("a.8o"), &[0x0]),
+            Format::OctoSource
+        );
+        assert_eq!(
+            Format::detect(Path::new("a.gif"), &gif),
+            Format::OctoCartridge
+        );
+        assert_eq!(Format::detect(Path::new("a"), &gif), Format::OctoCartridge);
+        assert_eq!(Format::detect(Path::new("a.rom"), &[0x0]), Format::Chip8);
+    }
+
+    #[test]
+    fn formats_have_a_platform_with_room_for_them() {
+        assert_eq!(Format::Chip8.platform(), Platform::COSMAC_VIP);
+        assert_eq!(Format::SuperChip.platform(), Platform::HP_48);
+        assert_eq!(Format::OctoSource.platform(), Platform::XO_CHIP);
+        for format in [Format::Chip8, Format::SuperChip, Format::XoChip] {
+            assert_eq!(format.platform().max_rom_size(), format.max_rom_size());
+        }
+    }
+
+    #[test]
+    fn octo_cartridges_hold_source() {
+        let gif = octo_cartridge(r#"{"program":": main\n  v0 := 1\n","options":{"tickrate":20}}"#);
+        assert_eq!(
+            octo_cartridge_source(&gif),
+            Ok(": main\n  v0 := 1\n".to_string())
+        );
+        assert!(octo_cartridge_source(&octo_cartridge("{}")).is_err());
+    }
+
+    #[test]
+    fn sizes_are_checked() {
+        let at_limit = Cartridge::new(vec![0x0; SMALL_ROM_SIZE], Format::Chip8, BTreeMap::new());
+        assert!(at_limit.unwrap().fits_platform());
+        let over = Cartridge::new(
+            vec![0x0; SMALL_ROM_SIZE + 1],
+            Format::Chip8,
+            BTreeMap::new(),
+        );
+        assert!(!over.unwrap().fits_platform());
+        let xo_chip = Cartridge::new(
+            vec![0x0; SMALL_ROM_SIZE + 1],
+            Format::XoChip,
+            BTreeMap::new(),
+        );
+        assert!(xo_chip.unwrap().fits_platform());
+        assert_eq!(
+            Cartridge::new(vec![0x0; MAX_ROM_SIZE + 1], Format::XoChip, BTreeMap::new()),
+            Err(CartridgeError::TooLarge {
+                size: MAX_ROM_SIZE + 1,
+                max: MAX_ROM_SIZE
+            })
+        );
+        assert_eq!(
+            Cartridge::new(Vec::new(), Format::Chip8, BTreeMap::new()),
+            Err(CartridgeError::Empty)
+        );
+    }
+
+    #[test]
+    fn roms_are_identified_by_sha1() {
+        let cartridge = Cartridge::new(b"abc".to_vec(), Format::Chip8, BTreeMap::new()).unwrap();
+        assert_eq!(
+            cartridge.sha1_hex(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
 }
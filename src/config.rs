@@ -0,0 +1,292 @@
+use crate::database::RomInfo;
+use crate::error::ConfigError;
+use crate::keymap::{Binding, ControllerMap, Hotkey, KeyMap, Layout, CONTROLLER_INPUTS};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// Settings read from TOML config files. The global file in the user's config directory is read first, then
+/// the one next to the ROM, e.g. pong.ch8.toml, so a ROM can override any of them.
+///
+/// ```toml
+/// layout = "azerty"           # The keypad preset: qwerty, azerty or dvorak.
+///
+/// [keypad]                    # CHIP-8 keys to host keys, replacing the preset's.
+/// 5 = ["W", "Up"]
+///
+/// [hotkeys]
+/// pause = "P"
+///
+/// [controller]                # Controller buttons and stick directions to CHIP-8 keys, or "none".
+/// deadzone = 0.3
+/// dpup = 1
+/// dpdown = "4"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub keymap: KeyMap,
+    pub controller: ControllerMap,
+}
+
+impl Config {
+    /// Reads the global config and the ROM's, if they exist, and checks the result. Keys the database has for
+    /// the ROM go in between, so its own file can still change them.
+    pub fn load(rom_path: &str, rom_info: Option<&RomInfo>) -> Result<Config, String> {
+        let mut config = Config::default();
+        if let Some(path) = global_path() {
+            config.apply_file(&path)?;
+        }
+        if let Some(info) = rom_info {
+            info.apply_keys(&mut config.controller);
+        }
+        config.apply_file(&PathBuf::from(format!("{}.toml", rom_path)))?;
+        config.keymap.validate().map_err(|e| e.to_string())?;
+        Ok(config)
+    }
+
+    /// Applies a config file, if it exists.
+    fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        match fs::read_to_string(path) {
+            Ok(text) => self
+                .apply(&text)
+                .map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    /// Applies the settings in a config file on top of the current ones.
+    pub fn apply(&mut self, text: &str) -> Result<(), ConfigError> {
+        let table = match text.parse::<Value>() {
+            Ok(Value::Table(table)) => table,
+            Ok(_) => return Err(ConfigError::Syntax("expected a table".to_string())),
+            Err(err) => return Err(ConfigError::Syntax(err.to_string())),
+        };
+        // The layout goes first so the keypad table can change parts of it.
+        if let Some(layout) = table.get("layout") {
+            let layout = layout
+                .as_str()
+                .and_then(Layout::from_name)
+                .ok_or_else(|| invalid("layout", "qwerty, azerty or dvorak"))?;
+            self.keymap.set_layout(layout);
+        }
+        for (name, value) in table.iter() {
+            match name.as_str() {
+                "layout" => (),
+                "keypad" => {
+                    for (key, keys) in table_of(name, value)? {
+                        let setting = format!("keypad.{}", key);
+                        let key = u8::from_str_radix(key, 16)
+                            .ok()
+                            .filter(|&key| key <= 0xf)
+                            .ok_or(ConfigError::UnknownSetting(setting.clone()))?;
+                        self.keymap
+                            .bind(Binding::Keypad(key), key_names(&setting, keys)?);
+                    }
+                }
+                "hotkeys" => {
+                    for (hotkey, keys) in table_of(name, value)? {
+                        let setting = format!("hotkeys.{}", hotkey);
+                        let hotkey = Hotkey::from_name(hotkey)
+                            .ok_or(ConfigError::UnknownSetting(setting.clone()))?;
+                        self.keymap
+                            .bind(Binding::Hotkey(hotkey), key_names(&setting, keys)?);
+                    }
+                }
+                "controller" => {
+                    for (input, key) in table_of(name, value)? {
+                        let setting = format!("controller.{}", input);
+                        if input == "deadzone" {
+                            self.controller.deadzone = key
+                                .as_float()
+                                .filter(|deadzone| (0.0..1.0).contains(deadzone))
+                                .ok_or_else(|| invalid(&setting, "a number from 0 to 1"))?
+                                as f32;
+                        } else if CONTROLLER_INPUTS.contains(&input.as_str()) {
+                            self.controller.bind(input, chip8_key(&setting, key)?);
+                        } else {
+                            return Err(ConfigError::UnknownSetting(setting));
+                        }
+                    }
+                }
+                _ => return Err(ConfigError::UnknownSetting(name.clone())),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the global config file lives: $XDG_CONFIG_HOME/chip_8/config.toml, falling back to ~/.config, or the
+/// application data folder on Windows.
+pub fn global_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(dir.join("chip_8").join("config.toml"))
+}
+
+fn invalid(setting: &str, expected: &'static str) -> ConfigError {
+    ConfigError::InvalidValue {
+        setting: setting.to_string(),
+        expected,
+    }
+}
+
+fn table_of<'a>(name: &str, value: &'a Value) -> Result<&'a toml::value::Table, ConfigError> {
+    value.as_table().ok_or_else(|| invalid(name, "a table"))
+}
+
+/// A CHIP-8 key as a number or hex digit, or "none".
+fn chip8_key(setting: &str, value: &Value) -> Result<Option<u8>, ConfigError> {
+    let key = match value {
+        Value::Integer(key) => Some(*key),
+        Value::String(key) if key == "none" => return Ok(None),
+        Value::String(key) => i64::from_str_radix(key, 16).ok(),
+        _ => None,
+    };
+    match key {
+        Some(key) if (0x0..=0xf).contains(&key) => Ok(Some(key as u8)),
+        _ => Err(invalid(setting, "a CHIP-8 key from 0 to F, or \"none\"")),
+    }
+}
+
+/// A key name, or a list of them.
+fn key_names(setting: &str, value: &Value) -> Result<Vec<String>, ConfigError> {
+    let expected = "a key name or a list of them";
+    match value {
+        Value::String(name) => Ok(vec![name.clone()]),
+        Value::Array(names) => names
+            .iter()
+            .map(|name| {
+                name.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid(setting, expected))
+            })
+            .collect(),
+        _ => Err(invalid(setting, expected)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound_to(config: &Config, name: &str) -> Vec<Binding> {
+        config
+            .keymap
+            .bindings()
+            .into_iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, binding)| binding)
+            .collect()
+    }
+
+    #[test]
+    fn the_default_is_qwerty() {
+        let config = Config::default();
+        assert_eq!(bound_to(&config, "W"), [Binding::Keypad(0x5)]);
+        assert_eq!(bound_to(&config, "V"), [Binding::Keypad(0xF)]);
+        assert_eq!(bound_to(&config, "Escape"), [Binding::Hotkey(Hotkey::Quit)]);
+        assert_eq!(config.keymap.validate(), Ok(()));
+    }
+
+    #[test]
+    fn presets_move_the_keypad() {
+        for (layout, key_for_4) in [("azerty", "A"), ("dvorak", "'")] {
+            let mut config = Config::default();
+            config.apply(&format!("layout = \"{}\"", layout)).unwrap();
+            assert_eq!(bound_to(&config, key_for_4), [Binding::Keypad(0x4)]);
+            assert_eq!(config.keymap.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn later_files_override_earlier_ones() {
+        let mut config = Config::default();
+        config
+            .apply("layout = \"azerty\"\n[hotkeys]\npause = \"P\"")
+            .unwrap();
+        config
+            .apply("[keypad]\n5 = [\"Up\", \"I\"]\nc = \"Tab\"")
+            .unwrap();
+        assert_eq!(bound_to(&config, "Up"), [Binding::Keypad(0x5)]);
+        assert_eq!(bound_to(&config, "I"), [Binding::Keypad(0x5)]);
+        assert_eq!(bound_to(&config, "Z"), []);
+        assert_eq!(bound_to(&config, "tab"), [Binding::Keypad(0xC)]);
+        assert_eq!(bound_to(&config, "P"), [Binding::Hotkey(Hotkey::Pause)]);
+        assert_eq!(bound_to(&config, "A"), [Binding::Keypad(0x4)]);
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        let mut config = Config::default();
+        config.apply("[hotkeys]\nreset = \"q\"").unwrap();
+        assert_eq!(
+            config.keymap.validate(),
+            Err(ConfigError::Conflict {
+                key: "Q".to_string(),
+                first: "keypad 4".to_string(),
+                second: "reset".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn save_slot_keys_are_reserved() {
+        let mut config = Config::default();
+        config.apply("[keypad]\n0 = \"F3\"").unwrap();
+        assert!(matches!(
+            config.keymap.validate(),
+            Err(ConfigError::ReservedKey { .. })
+        ));
+    }
+
+    #[test]
+    fn controllers_can_be_remapped_per_rom() {
+        let mut config = Config::default();
+        config
+            .apply("[controller]\ndeadzone = 0.5\ndpup = 1\ndpdown = \"4\"\n\"lefty+\" = \"c\"\na = \"none\"")
+            .unwrap();
+        let bindings = config.controller.bindings();
+        assert!(bindings.contains(&("dpup".to_string(), 0x1)));
+        assert!(bindings.contains(&("dpdown".to_string(), 0x4)));
+        assert!(bindings.contains(&("lefty+".to_string(), 0xC)));
+        assert!(bindings.iter().all(|(input, _)| input != "a"));
+        assert_eq!(config.controller.deadzone, 0.5);
+        assert_eq!(
+            config.apply("[controller]\nwhammy = 1"),
+            Err(ConfigError::UnknownSetting("controller.whammy".to_string()))
+        );
+        assert!(config.apply("[controller]\nb = 16").is_err());
+        assert!(config.apply("[controller]\ndeadzone = 2.0").is_err());
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        let mut config = Config::default();
+        assert!(matches!(
+            config.apply("layout = \"colemak\""),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert_eq!(
+            config.apply("[keypad]\n10 = \"Q\""),
+            Err(ConfigError::UnknownSetting("keypad.10".to_string()))
+        );
+        assert_eq!(
+            config.apply("[hotkeys]\nfast = \"Q\""),
+            Err(ConfigError::UnknownSetting("hotkeys.fast".to_string()))
+        );
+        assert!(matches!(
+            config.apply("[keypad]\n1 = 1"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            config.apply("[keypad"),
+            Err(ConfigError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::keymap::ControllerMap;
+use crate::platform::Platform;
+use crate::quirks::{IndexIncrement, Quirks};
+use serde_json::Value;
+
+/// The database, in the format of the CHIP-8 community's programs.json (github.com/chip-8/chip-8-database), built
+/// into the emulator. That file can be copied over this one to recognise more ROMs.
+const PROGRAMS: &str = include_str!("../database/programs.json");
+
+/// What the database knows about a ROM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Option<String>, // The database's name for the preferred platform the emulator has quirks for.
+    pub quirks: Option<Quirks>,   // The quirks of that platform, with any the ROM needs changed.
+    pub layout: Option<Platform>, // The memory layout of that platform.
+    pub ipf: Option<u32>,         // Instructions per 60 Hz frame.
+    pub keys: Vec<(String, u8)>,  // What "up", "down", "a" and so on are, as CHIP-8 keys.
+    pub palette: Option<[(u8, u8, u8); 4]>,
+}
+
+impl RomInfo {
+    /// Looks up a ROM by its SHA-1, in lower case hex, in the built-in database.
+    pub fn lookup(sha1: &str) -> Option<RomInfo> {
+        let programs =
+            serde_json::from_str(PROGRAMS).expect("The built-in database is not valid JSON.");
+        RomInfo::find(&programs, sha1)
+    }
+
+    /// Looks up a ROM in a parsed programs.json.
+    pub fn find(programs: &Value, sha1: &str) -> Option<RomInfo> {
+        for program in programs.as_array()? {
+            let rom = match program.get("roms").and_then(|roms| roms.get(sha1)) {
+                Some(rom) => rom,
+                None => continue,
+            };
+            let platform = rom
+                .get("platforms")
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Value::as_str)
+                .find(|&platform| platform_quirks(platform).is_some());
+            let quirks = platform.and_then(|platform| {
+                let quirks = platform_quirks(platform)?;
+                let changes = rom.get("quirkyPlatforms").and_then(|q| q.get(platform));
+                Some(changes.map_or(quirks, |changes| quirky(quirks, changes)))
+            });
+            return Some(RomInfo {
+                title: program
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Untitled")
+                    .to_string(),
+                authors: program
+                    .get("authors")
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect(),
+                platform: platform.map(str::to_string),
+                quirks,
+                layout: platform.and_then(platform_layout),
+                ipf: rom
+                    .get("tickrate")
+                    .and_then(Value::as_f64)
+                    .filter(|&ipf| ipf >= 1.0)
+                    .map(|ipf| ipf as u32),
+                keys: rom
+                    .get("keys")
+                    .and_then(Value::as_object)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(input, key)| {
+                        let key = key.as_f64().filter(|key| (0.0..16.0).contains(key))?;
+                        Some((input.clone(), key as u8))
+                    })
+                    .collect(),
+                palette: rom
+                    .get("colors")
+                    .and_then(|colors| colors.get("pixels"))
+                    .and_then(Value::as_array)
+                    .and_then(|colours| palette(colours)),
+            });
+        }
+        None
+    }
+
+    /// Points the controller's D-pad, left stick and face buttons at the keys the ROM uses.
+    pub fn apply_keys(&self, controller: &mut ControllerMap) {
+        for (input, key) in self.keys.iter() {
+            let controls: &[&str] = match input.as_str() {
+                "up" => &["dpup", "lefty-"],
+                "down" => &["dpdown", "lefty+"],
+                "left" => &["dpleft", "leftx-"],
+                "right" => &["dpright", "leftx+"],
+                "a" => &["a"],
+                "b" => &["b"],
+                _ => &[],
+            };
+            for control in controls {
+                controller.bind(control, Some(*key));
+            }
+        }
+    }
+}
+
+/// The emulator's profile for a platform named in the database, if it has one.
+fn platform_quirks(platform: &str) -> Option<Quirks> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(Quirks::COSMAC_VIP),
+        "modernChip8" => Some(Quirks::MODERN),
+        "chip48" => Some(Quirks::CHIP_48),
+        "superchip1" | "superchip" => Some(Quirks::SUPER_CHIP),
+        "xochip" => Some(Quirks::XO_CHIP),
+        _ => None,
+    }
+}
+
+/// The memory layout of a platform named in the database. Programs for modern interpreters get as much memory as
+/// XO-CHIP's.
+fn platform_layout(platform: &str) -> Option<Platform> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(Platform::COSMAC_VIP),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::HP_48),
+        "modernChip8" | "xochip" => Some(Platform::XO_CHIP),
+        _ => None,
+    }
+}
+
+/// Changes a platform's quirks as the database says a ROM needs.
+fn quirky(mut quirks: Quirks, changes: &Value) -> Quirks {
+    let flag = |name| changes.get(name).and_then(Value::as_bool);
+    if let Some(shift) = flag("shift") {
+        quirks.shift_uses_vy = !shift;
+    }
+    if flag("memoryLeaveIUnchanged") == Some(true) {
+        quirks.index_increment = IndexIncrement::Unchanged;
+    } else if flag("memoryIncrementByX") == Some(true) {
+        quirks.index_increment = IndexIncrement::ByX;
+    } else if flag("memoryIncrementByX") == Some(false) {
+        quirks.index_increment = IndexIncrement::ByXPlusOne;
+    }
+    if let Some(wrap) = flag("wrap") {
+        quirks.sprite_wrap = wrap;
+    }
+    if let Some(jump) = flag("jump") {
+        quirks.jump_uses_vx = jump;
+    }
+    if let Some(logic) = flag("logic") {
+        quirks.logic_resets_vf = logic;
+    }
+    quirks
+}
+
+/// Up to four "#rrggbb" colours for the combinations of the two bitplanes.
+fn palette(colours: &[Value]) -> Option<[(u8, u8, u8); 4]> {
+    let mut palette = crate::framebuffer::PALETTE;
+    for (entry, colour) in palette.iter_mut().zip(colours) {
+        let hex = colour.as_str()?.strip_prefix('#')?;
+        let rgb = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        *entry = ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+    }
+    Some(palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn programs() -> Value {
+        serde_json::from_str(&format!(
+            r##"[
+                {{"title": "Other", "roms": {{"ffff": {{"platforms": ["modernChip8"]}}}}}},
+                {{
+                    "title": "Paddles",
+                    "authors": ["A. Author"],
+                    "roms": {{
+                        "{}": {{
+                            "platforms": ["megachip8", "superchip", "xochip"],
+                            "tickrate": 30,
+                            "keys": {{"up": 1, "down": 4, "player2Up": 12}},
+                            "colors": {{"pixels": ["#102030", "#ffeedd"]}},
+                            "quirkyPlatforms": {{"superchip": {{"wrap": true, "memoryIncrementByX": true}}}}
+                        }}
+                    }}
+                }}
+            ]"##,
+            SHA1
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn roms_are_found_by_sha1() {
+        let info = RomInfo::find(&programs(), SHA1).unwrap();
+        assert_eq!(info.title, "Paddles");
+        assert_eq!(info.authors, ["A. Author"]);
+        assert_eq!(info.ipf, Some(30));
+        assert_eq!(
+            info.palette.map(|palette| palette[..2].to_vec()),
+            Some(vec![(0x10, 0x20, 0x30), (0xff, 0xee, 0xdd)])
+        );
+        assert_eq!(RomInfo::find(&programs(), "abcd"), None);
+    }
+
+    #[test]
+    fn the_first_platform_with_a_profile_is_used() {
+        let info = RomInfo::find(&programs(), SHA1).unwrap();
+        assert_eq!(info.platform.as_deref(), Some("superchip"));
+        let quirks = info.quirks.unwrap();
+        assert!(quirks.sprite_wrap);
+        assert_eq!(quirks.index_increment, IndexIncrement::ByX);
+        assert_eq!(quirks.jump_uses_vx, Quirks::SUPER_CHIP.jump_uses_vx);
+        assert_eq!(info.layout, Some(Platform::HP_48));
+    }
+
+    #[test]
+    fn keys_move_the_controller() {
+        let mut controller = ControllerMap::default();
+        RomInfo::find(&programs(), SHA1)
+            .unwrap()
+            .apply_keys(&mut controller);
+        let bindings = controller.bindings();
+        assert!(bindings.contains(&("dpup".to_string(), 0x1)));
+        assert!(bindings.contains(&("lefty+".to_string(), 0x4)));
+        assert!(bindings.contains(&("a".to_string(), 0x6)));
+    }
+
+    #[test]
+    fn the_built_in_database_is_a_list_of_programs() {
+        let programs: Value = serde_json::from_str(PROGRAMS).unwrap();
+        assert!(programs.is_array());
+        assert_eq!(RomInfo::lookup(SHA1), RomInfo::find(&programs, SHA1));
+    }
+}
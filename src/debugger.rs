@@ -0,0 +1,548 @@
+use crate::disasm;
+use crate::processor::Processor;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "Commands:
+    s, step [n]            Execute n instructions, 1 by default.
+    c, continue            Run until a breakpoint or watch triggers.
+    b, break [addr]        Break at an address or label, or list breakpoints and watches.
+    bo, break-op <opcode>  Break on opcodes matching a pattern, e.g. D01F or Dxyn. Non-hex digits are wildcards.
+    w, watch <location>    Break after a write changes v0-vf, i, sp, dt, st or the memory byte at a hex address.
+    d, delete [item]       Remove a breakpoint, opcode pattern or watch, or all of them.
+    r, regs                Print all registers.
+    p, print <item>        Print v0-vf, i, pc, sp, stack, dt, st or timers.
+    m, mem <addr> [len]    Dump len bytes of memory, 64 by default.
+    q, quit                Stop the emulator.
+    h, help                Show this message.";
+
+/// A register or memory byte whose writes are watched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Location {
+    V(u8),
+    I,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    Memory(u16),
+}
+
+impl Location {
+    fn parse(text: &str) -> Option<Location> {
+        let text = text.to_lowercase();
+        match text.as_str() {
+            "i" => Some(Location::I),
+            "sp" => Some(Location::Sp),
+            "dt" => Some(Location::DelayTimer),
+            "st" => Some(Location::SoundTimer),
+            _ if text.len() == 2 && text.starts_with('v') => {
+                u8::from_str_radix(&text[1..], 16).ok().map(Location::V)
+            }
+            _ => parse_address(&text).map(Location::Memory),
+        }
+    }
+
+    fn read(self, cpu: &Processor) -> u16 {
+        match self {
+            Location::V(x) => cpu.v()[x as usize] as u16,
+            Location::I => cpu.i(),
+            Location::Sp => cpu.sp() as u16,
+            Location::DelayTimer => cpu.delay_timer() as u16,
+            Location::SoundTimer => cpu.sound_timer() as u16,
+            Location::Memory(addr) => cpu.memory().get(addr as usize).copied().unwrap_or(0) as u16,
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Location::V(x) => format!("V{:X}", x),
+            Location::I => "I".to_string(),
+            Location::Sp => "SP".to_string(),
+            Location::DelayTimer => "DT".to_string(),
+            Location::SoundTimer => "ST".to_string(),
+            Location::Memory(addr) => format!("[{:04X}]", addr),
+        }
+    }
+}
+
+/// An opcode with some nibbles left as wildcards, e.g. Dxyn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpcodePattern {
+    value: u16,
+    mask: u16, // Set for the nibbles that must match.
+}
+
+impl OpcodePattern {
+    pub fn parse(text: &str) -> Option<OpcodePattern> {
+        if text.chars().count() != 4 {
+            return None;
+        }
+        let mut value = 0;
+        let mut mask = 0;
+        for c in text.chars() {
+            value <<= 4;
+            mask <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                value |= digit as u16;
+                mask |= 0xf;
+            }
+        }
+        Some(OpcodePattern { value, mask })
+    }
+
+    pub fn matches(self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl std::fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for shift in [12, 8, 4, 0].iter() {
+            if (self.mask >> shift) & 0xf == 0 {
+                write!(f, "?")?;
+            } else {
+                write!(f, "{:X}", (self.value >> shift) & 0xf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the frontend should do once the prompt returns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugAction {
+    Resume,
+    Quit,
+}
+
+/// Breakpoints, watches and single stepping, driven from a command prompt.
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    opcode_breaks: Vec<OpcodePattern>,
+    watches: Vec<(Location, u16)>, // Each location with its value after the last instruction.
+    steps: Option<u32>,            // Instructions left before pausing, or None when continuing.
+    resume_pc: Option<u16>, // Where execution resumed, so its breakpoint does not trigger again at once.
+    reason: Option<String>, // Why execution stopped, shown at the next prompt.
+    labels: BTreeMap<u16, String>, // Names for addresses, from the program's source.
+}
+
+impl Debugger {
+    /// Starts paused at the first instruction.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            opcode_breaks: Vec::new(),
+            watches: Vec::new(),
+            steps: Some(0),
+            resume_pc: None,
+            reason: None,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Names addresses after the labels of the program's source, e.g. from octo::Program. Labels are shown in
+    /// the disassembly and accepted wherever an address is.
+    pub fn set_labels(&mut self, labels: &BTreeMap<String, u16>) {
+        self.labels = labels
+            .iter()
+            .map(|(name, &addr)| (addr, name.clone()))
+            .collect();
+    }
+
+    /// Called before each instruction. Returns true if execution should pause and the prompt be shown.
+    pub fn should_pause(&mut self, cpu: &Processor) -> bool {
+        let pc = cpu.pc();
+        let resumed_here = self.resume_pc.take() == Some(pc);
+        if let Some(steps) = self.steps {
+            if steps == 0 {
+                return true;
+            }
+            self.steps = Some(steps - 1);
+        }
+        if resumed_here {
+            return false;
+        }
+        if self.breakpoints.contains(&pc) {
+            self.reason = Some(format!("Breakpoint at {:04X}.", pc));
+            return true;
+        }
+        let opcode = current_opcode(cpu);
+        if let Some(pattern) = self.opcode_breaks.iter().find(|p| p.matches(opcode)) {
+            self.reason = Some(format!("Opcode {:04X} matches {}.", opcode, pattern));
+            return true;
+        }
+        false
+    }
+
+    /// Called after each instruction to check the watches. A change pauses before the next instruction.
+    pub fn after_instruction(&mut self, cpu: &Processor) {
+        let mut changes = Vec::new();
+        for (location, last) in self.watches.iter_mut() {
+            let value = location.read(cpu);
+            if value != *last {
+                changes.push(format!(
+                    "Watch {}: {:02X} -> {:02X}",
+                    location.name(),
+                    last,
+                    value
+                ));
+                *last = value;
+            }
+        }
+        if !changes.is_empty() {
+            self.reason = Some(changes.join("\n"));
+            self.steps = Some(0);
+        }
+    }
+
+    /// Reads and runs commands until one resumes execution or quits.
+    pub fn prompt<R: BufRead, W: Write>(
+        &mut self,
+        cpu: &Processor,
+        input: &mut R,
+        output: &mut W,
+    ) -> DebugAction {
+        if let Some(reason) = self.reason.take() {
+            writeln!(output, "{}", reason).ok();
+        }
+        let opcode = current_opcode(cpu);
+        let name = |addr: u16| match self.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("{:#05X}", addr),
+        };
+        let long = word_at(cpu, cpu.pc() as usize + 2);
+        let mnemonic = disasm::decode_with(opcode, long, &name).unwrap_or_default();
+        if let Some(label) = self.labels.get(&cpu.pc()) {
+            writeln!(output, "{}:", label).ok();
+        }
+        writeln!(output, "{:04X}: {:04X}  {}", cpu.pc(), opcode, mnemonic).ok();
+        loop {
+            write!(output, "(chip8) ").ok();
+            output.flush().ok();
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => return DebugAction::Quit,
+                Ok(_) => (),
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (command, args) = match words.split_first() {
+                Some((command, args)) => (*command, args),
+                None => continue,
+            };
+            match self.execute(cpu, command, args, output) {
+                Ok(Some(action)) => {
+                    self.resume_pc = Some(cpu.pc());
+                    return action;
+                }
+                Ok(None) => (),
+                Err(message) => {
+                    writeln!(output, "{}", message).ok();
+                }
+            }
+        }
+    }
+
+    fn execute<W: Write>(
+        &mut self,
+        cpu: &Processor,
+        command: &str,
+        args: &[&str],
+        output: &mut W,
+    ) -> Result<Option<DebugAction>, String> {
+        match command {
+            "s" | "step" => {
+                let steps: u32 = match args.first() {
+                    Some(n) => n
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid count '{}'.", n))?,
+                    None => 1,
+                };
+                // The instruction at the prompt runs without being counted.
+                self.steps = Some(steps - 1);
+                return Ok(Some(DebugAction::Resume));
+            }
+            "c" | "continue" => {
+                self.steps = None;
+                return Ok(Some(DebugAction::Resume));
+            }
+            "b" | "break" => match args.first() {
+                Some(addr) => {
+                    let addr = self.address(addr).ok_or("Invalid address.")?;
+                    if !self.breakpoints.contains(&addr) {
+                        self.breakpoints.push(addr);
+                    }
+                }
+                None => self.list(output),
+            },
+            "bo" | "break-op" => {
+                let pattern = args.first().ok_or("break-op requires an opcode pattern.")?;
+                let pattern = OpcodePattern::parse(pattern).ok_or("Patterns are 4 digits long.")?;
+                self.opcode_breaks.push(pattern);
+            }
+            "w" | "watch" => {
+                let location = args.first().ok_or("watch requires a location.")?;
+                let location = Location::parse(location).ok_or("Invalid location.")?;
+                self.watches.push((location, location.read(cpu)));
+            }
+            "d" | "delete" => match args.first() {
+                Some(item) => self.delete(item)?,
+                None => {
+                    self.breakpoints.clear();
+                    self.opcode_breaks.clear();
+                    self.watches.clear();
+                }
+            },
+            "r" | "regs" => {
+                writeln!(output, "{}", cpu.register_dump()).ok();
+            }
+            "p" | "print" => {
+                let item = args.first().ok_or("print requires a register.")?;
+                writeln!(output, "{}", print(cpu, item)?).ok();
+            }
+            "m" | "mem" => {
+                let addr = args.first().ok_or("mem requires an address.")?;
+                let addr = self.address(addr).ok_or("Invalid address.")? as usize;
+                let len = match args.get(1) {
+                    Some(len) => len.parse().map_err(|_| "Invalid length.")?,
+                    None => 64,
+                };
+                dump_memory(cpu, addr, len, output);
+            }
+            "q" | "quit" => return Ok(Some(DebugAction::Quit)),
+            "h" | "help" => {
+                writeln!(output, "{}", HELP).ok();
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown command '{}'. Type help for a list.",
+                    command
+                ))
+            }
+        }
+        Ok(None)
+    }
+
+    /// A label or a hex address.
+    fn address(&self, text: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == text)
+            .map(|(&addr, _)| addr)
+            .or_else(|| parse_address(text))
+    }
+
+    fn list<W: Write>(&self, output: &mut W) {
+        for addr in self.breakpoints.iter() {
+            writeln!(output, "break {:04X}", addr).ok();
+        }
+        for pattern in self.opcode_breaks.iter() {
+            writeln!(output, "break-op {}", pattern).ok();
+        }
+        for (location, _) in self.watches.iter() {
+            writeln!(output, "watch {}", location.name()).ok();
+        }
+    }
+
+    fn delete(&mut self, item: &str) -> Result<(), String> {
+        let count = self.breakpoints.len() + self.opcode_breaks.len() + self.watches.len();
+        if let Some(location) = Location::parse(item) {
+            self.watches.retain(|(watched, _)| *watched != location);
+        }
+        if let Some(addr) = self.address(item) {
+            self.breakpoints.retain(|&breakpoint| breakpoint != addr);
+        }
+        if let Some(pattern) = OpcodePattern::parse(item) {
+            self.opcode_breaks.retain(|&p| p != pattern);
+        }
+        if count == self.breakpoints.len() + self.opcode_breaks.len() + self.watches.len() {
+            return Err(format!("Nothing matches '{}'.", item));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+/// Accepts addresses such as 200, 0x200 or #200.
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .trim_start_matches('#');
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn current_opcode(cpu: &Processor) -> u16 {
+    word_at(cpu, cpu.pc() as usize).unwrap_or(0x0)
+}
+
+fn word_at(cpu: &Processor, addr: usize) -> Option<u16> {
+    let memory = cpu.memory();
+    match (memory.get(addr), memory.get(addr + 1)) {
+        (Some(&high), Some(&low)) => Some((high as u16) << 8 | low as u16),
+        _ => None,
+    }
+}
+
+fn print(cpu: &Processor, item: &str) -> Result<String, String> {
+    let item = item.to_lowercase();
+    Ok(match item.as_str() {
+        "pc" => format!("PC={:04X}", cpu.pc()),
+        "stack" => {
+            let stack: Vec<String> = cpu.stack().iter().map(|a| format!("{:04X}", a)).collect();
+            format!("Stack=[{}]", stack.join(" "))
+        }
+        "timers" => format!("DT={:02X} ST={:02X}", cpu.delay_timer(), cpu.sound_timer()),
+        "v" => {
+            let v: Vec<String> = cpu
+                .v()
+                .iter()
+                .enumerate()
+                .map(|(x, value)| format!("V{:X}={:02X}", x, value))
+                .collect();
+            v.join(" ")
+        }
+        _ => match Location::parse(&item) {
+            Some(Location::I) => format!("I={:04X}", cpu.i()),
+            Some(location) => format!("{}={:02X}", location.name(), location.read(cpu)),
+            None => return Err(format!("Unknown register '{}'.", item)),
+        },
+    })
+}
+
+fn dump_memory<W: Write>(cpu: &Processor, addr: usize, len: usize, output: &mut W) {
+    let memory = cpu.memory();
+    let addr = addr.min(memory.len());
+    let end = addr.saturating_add(len).min(memory.len());
+    for row in (addr..end).step_by(16) {
+        let bytes: Vec<String> = memory[row..(row + 16).min(end)]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        writeln!(output, "{:04X}: {}", row, bytes.join(" ")).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    const PROGRAM: [u8; 8] = [
+        0x60, 0x01, // 200: v0 := 1
+        0x70, 0x01, // 202: v0 += 1
+        0xA3, 0x00, // 204: i := 0x300
+        0x12, 0x02, // 206: jump 0x202
+    ];
+
+    /// Runs the program under the debugger, answering its prompts from a script, until it quits or 100
+    /// instructions have run. Returns the processor and everything the debugger printed.
+    fn debug(debugger: &mut Debugger, script: &str) -> (Processor, String) {
+        let mut cpu = Processor::new(Quirks::MODERN);
+        cpu.load_cartridge(&PROGRAM);
+        let mut input = script.as_bytes();
+        let mut output = Vec::new();
+        for _ in 0..100 {
+            if debugger.should_pause(&cpu)
+                && debugger.prompt(&cpu, &mut input, &mut output) == DebugAction::Quit
+            {
+                break;
+            }
+            cpu.cycle([false; 16]).unwrap();
+            debugger.after_instruction(&cpu);
+        }
+        (cpu, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn breakpoints_stop_each_time_they_are_reached() {
+        let (cpu, output) = debug(&mut Debugger::new(), "b 206\nc\nc\nq\n");
+        assert_eq!(cpu.pc(), 0x206);
+        assert_eq!(output.matches("Breakpoint at 0206.").count(), 2);
+
+        let (cpu, output) = debug(&mut Debugger::new(), "bo Axxx\nc\nq\n");
+        assert_eq!(cpu.pc(), 0x204);
+        assert!(output.contains("Opcode A300 matches A???."));
+    }
+
+    #[test]
+    fn labels_name_addresses() {
+        let mut debugger = Debugger::new();
+        let mut labels = BTreeMap::new();
+        labels.insert("loop".to_string(), 0x202);
+        debugger.set_labels(&labels);
+        let (cpu, output) = debug(&mut debugger, "b loop\nc\nq\n");
+        assert_eq!(cpu.pc(), 0x202);
+        assert!(output.contains("loop:\n0202: 7001  ADD V0, 0x01"));
+    }
+
+    #[test]
+    fn steps_run_that_many_instructions() {
+        let (cpu, output) = debug(&mut Debugger::new(), "s\ns 2\nq\n");
+        assert_eq!(cpu.pc(), 0x206);
+        assert!(output.contains("0202: 7001"));
+        assert!(!output.contains("0204: A300"));
+        assert_eq!(cpu.v()[0], 0x2);
+    }
+
+    #[test]
+    fn watches_stop_after_a_change() {
+        let (cpu, output) = debug(&mut Debugger::new(), "w v0\nc\nc\nq\n");
+        assert_eq!(cpu.pc(), 0x204);
+        assert!(output.contains("Watch V0: 00 -> 01"));
+        assert!(output.contains("Watch V0: 01 -> 02"));
+
+        let (cpu, output) = debug(&mut Debugger::new(), "w i\nc\nq\n");
+        assert_eq!(cpu.pc(), 0x206);
+        assert!(output.contains("Watch I: 00 -> 300"));
+    }
+
+    #[test]
+    fn delete_removes_one_item_or_all() {
+        let script = "b 206\nbo Dxyn\nw v0\nd 206\nb\nd\nb\nd v0\nq\n";
+        let (cpu, output) = debug(&mut Debugger::new(), script);
+        assert_eq!(cpu.pc(), 0x200);
+        assert!(!output.contains("break 0206"));
+        assert!(output.contains(
+            "(chip8) break-op D???\nwatch V0\n(chip8) (chip8) (chip8) Nothing matches 'v0'."
+        ));
+    }
+
+    #[test]
+    fn bad_commands_are_reported() {
+        let script = "s x\ns 0\nb zzz\nbo 12\nw q\np xx\nm\nm 200 x\nfrobnicate\nq\n";
+        let (cpu, output) = debug(&mut Debugger::new(), script);
+        assert_eq!(cpu.pc(), 0x200);
+        for message in [
+            "Invalid count 'x'.",
+            "Invalid count '0'.",
+            "Invalid address.",
+            "Patterns are 4 digits long.",
+            "Invalid location.",
+            "Unknown register 'xx'.",
+            "mem requires an address.",
+            "Invalid length.",
+            "Unknown command 'frobnicate'. Type help for a list.",
+        ] {
+            assert!(output.contains(message), "no {:?} in {}", message, output);
+        }
+    }
+
+    #[test]
+    fn memory_dumps_stop_at_the_end_of_memory() {
+        let (cpu, output) = debug(
+            &mut Debugger::new(),
+            "m 200 4\nm fff0 18446744073709551615\nq\n",
+        );
+        assert!(output.contains("0200: 60 01 70 01\n"));
+        let last = cpu.memory().len() - 16;
+        assert!(output.contains(&format!("{:04X}: 00 00", last)));
+        assert!(!output.contains(&format!("{:04X}:", cpu.memory().len())));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::platform::Platform;
+use std::collections::{BTreeSet, HashMap};
+
+/// Memory is at most 64 KB, so no ROM reaches past 0xFFFF.
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// The kind of label generated for an address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LabelKind {
+    Code, // Target of a jump or call.
+    Data, // Target of LD I, addr.
+}
+
+/// Decodes an opcode into Cowgod style assembly with addresses written as hex numbers.
+/// XO-CHIP's F000 is four bytes long; long is the word following it.
+pub fn decode(opcode: u16, long: Option<u16>) -> Option<String> {
+    decode_with(opcode, long, &|addr| format!("{:#05X}", addr))
+}
+
+/// Decodes an opcode, naming addresses with the given function.
+pub fn decode_with(opcode: u16, long: Option<u16>, name: &dyn Fn(u16) -> String) -> Option<String> {
+    let x = (opcode & 0x0f00) >> 8;
+    let y = (opcode & 0x00f0) >> 4;
+    let nnn = opcode & 0x0fff;
+    let kk = opcode & 0x00ff;
+    let n = opcode & 0x000f;
+    let text = match opcode >> 12 {
+        0x0 => match nnn {
+            0x0C0..=0x0CF => format!("SCD {}", n),
+            0x0D0..=0x0DF => format!("SCU {}", n),
+            0x0E0 => "CLS".to_string(),
+            0x0EE => "RET".to_string(),
+            0x0FB => "SCR".to_string(),
+            0x0FC => "SCL".to_string(),
+            0x0FD => "EXIT".to_string(),
+            0x0FE => "LOW".to_string(),
+            0x0FF => "HIGH".to_string(),
+            _ => format!("SYS {}", name(nnn)),
+        },
+        0x1 => format!("JP {}", name(nnn)),
+        0x2 => format!("CALL {}", name(nnn)),
+        0x3 => format!("SE V{:X}, {:#04X}", x, kk),
+        0x4 => format!("SNE V{:X}, {:#04X}", x, kk),
+        0x5 => match n {
+            0x0 => format!("SE V{:X}, V{:X}", x, y),
+            0x2 => format!("LD [I], V{:X} - V{:X}", x, y),
+            0x3 => format!("LD V{:X} - V{:X}, [I]", x, y),
+            _ => return None,
+        },
+        0x6 => format!("LD V{:X}, {:#04X}", x, kk),
+        0x7 => format!("ADD V{:X}, {:#04X}", x, kk),
+        0x8 => {
+            let operation = match n {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return None,
+            };
+            format!("{} V{:X}, V{:X}", operation, x, y)
+        }
+        0x9 if n == 0x0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {}", name(nnn)),
+        0xB => format!("JP V0, {}", name(nnn)),
+        0xC => format!("RND V{:X}, {:#04X}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => return None,
+        },
+        0xF => match kk {
+            0x00 if x == 0x0 => format!("LD I, long {}", name(long?)),
+            0x01 => format!("PLANE {}", x),
+            0x02 if x == 0x0 => "AUDIO".to_string(),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// One line of a listing: an instruction, or a byte of data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    pub text: String,
+    pub comment: Option<String>,
+}
+
+/// Disassembles a ROM loaded at the platform's load address.
+/// Only bytes reachable by following execution from the start address are decoded as instructions; everything
+/// else is data.
+pub fn disassemble(rom: &[u8], platform: &Platform) -> Vec<Line> {
+    // Addresses are kept as usize so that stepping past the last byte of memory cannot wrap around to 0x0.
+    let origin = platform.load_address as usize;
+    let rom = &rom[..rom.len().min(ADDRESS_SPACE.saturating_sub(origin))];
+    let word = |addr: usize| -> Option<u16> {
+        let offset = addr.checked_sub(origin)?;
+        let high = *rom.get(offset)?;
+        let low = *rom.get(offset + 1)?;
+        Some((high as u16) << 8 | low as u16)
+    };
+
+    let (code, mut labels) = trace(&word, platform.start_address as usize);
+
+    // Lay out the lines first, so that labels pointing outside the ROM or into the middle of an instruction can
+    // be dropped before any operand refers to them.
+    let mut layout = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = origin + offset;
+        let length = match word(addr) {
+            Some(opcode) if code.contains(&addr) => {
+                if opcode == 0xF000 {
+                    4
+                } else {
+                    2
+                }
+            }
+            _ => 1,
+        };
+        let length = length.min(rom.len() - offset);
+        layout.push((offset, length));
+        offset += length;
+    }
+    let starts: BTreeSet<usize> = layout.iter().map(|&(offset, _)| origin + offset).collect();
+    labels.retain(|&addr, _| starts.contains(&(addr as usize)));
+
+    let label_name = |addr: u16| match labels.get(&addr) {
+        Some(LabelKind::Code) => format!("L{:03X}", addr),
+        Some(LabelKind::Data) => format!("D{:03X}", addr),
+        None => format!("{:#05X}", addr),
+    };
+    layout
+        .into_iter()
+        .map(|(offset, length)| {
+            let addr = origin + offset;
+            let address = addr as u16;
+            let bytes = rom[offset..offset + length].to_vec();
+            let label = labels.get(&address).map(|_| label_name(address));
+            let decoded = word(addr)
+                .filter(|_| length > 1)
+                .and_then(|opcode| decode_with(opcode, word(addr + 2), &label_name));
+            match decoded {
+                Some(text) => Line {
+                    address,
+                    bytes,
+                    label,
+                    text,
+                    comment: None,
+                },
+                None => Line {
+                    address,
+                    text: format!("db {:#04X}", bytes[0]),
+                    comment: Some(sprite_row(bytes[0])),
+                    bytes,
+                    label,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Formats a listing as text, one instruction or data byte per line.
+pub fn listing(rom: &[u8], platform: &Platform) -> String {
+    let mut text = String::new();
+    for line in disassemble(rom, platform) {
+        if let Some(label) = &line.label {
+            text.push_str(&format!("{}:\n", label));
+        }
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        text.push_str(&format!(
+            "    {:04X}  {:<10}{}",
+            line.address,
+            bytes.join(""),
+            line.text
+        ));
+        if let Some(comment) = &line.comment {
+            text.push_str(&format!(
+                "{:>w$}; {}",
+                "",
+                comment,
+                w = 20 - line.text.len().min(20)
+            ));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Follows every path of execution from the start address, returning the addresses of the instructions reached
+/// and the addresses that deserve labels.
+fn trace(
+    word: &dyn Fn(usize) -> Option<u16>,
+    start: usize,
+) -> (BTreeSet<usize>, HashMap<u16, LabelKind>) {
+    let mut code = BTreeSet::new();
+    let mut labels = HashMap::new();
+    let mut pending = vec![start];
+    labels.insert(start as u16, LabelKind::Code);
+    while let Some(addr) = pending.pop() {
+        if code.contains(&addr) {
+            continue;
+        }
+        let opcode = match word(addr) {
+            Some(opcode) if decode(opcode, word(addr + 2)).is_some() => opcode,
+            _ => continue,
+        };
+        code.insert(addr);
+        let next = addr + if opcode == 0xF000 { 4 } else { 2 };
+        let nnn = opcode & 0x0fff;
+        match opcode >> 12 {
+            0x0 if opcode == 0x00EE || opcode == 0x00FD => (),
+            // A computed jump, assumed to land in a table starting at nnn.
+            0x1 | 0xB => {
+                labels.insert(nnn, LabelKind::Code);
+                pending.push(nnn as usize);
+            }
+            0x2 => {
+                labels.insert(nnn, LabelKind::Code);
+                pending.push(nnn as usize);
+                pending.push(next);
+            }
+            0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+                let skipped = if word(next) == Some(0xF000) { 4 } else { 2 };
+                pending.push(next);
+                pending.push(next + skipped);
+            }
+            0xA => {
+                labels.entry(nnn).or_insert(LabelKind::Data);
+                pending.push(next);
+            }
+            _ => {
+                if opcode == 0xF000 {
+                    if let Some(long) = word(addr + 2) {
+                        labels.entry(long).or_insert(LabelKind::Data);
+                    }
+                }
+                pending.push(next);
+            }
+        }
+    }
+    (code, labels)
+}
+
+/// Draws a byte as a row of sprite pixels, e.g. ..####..
+fn sprite_row(byte: u8) -> String {
+    (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::MAX_ROM_SIZE;
+
+    fn lines(rom: &[u8]) -> Vec<(u16, String)> {
+        disassemble(rom, &Platform::default())
+            .into_iter()
+            .map(|line| (line.address, line.text))
+            .collect()
+    }
+
+    #[test]
+    fn data_after_a_jump_is_left_as_bytes() {
+        let rom = [0x12, 0x04, 0xAB, 0xCD, 0x00, 0xFD];
+        assert_eq!(
+            lines(&rom),
+            [
+                (0x200, "JP L204".to_string()),
+                (0x202, "db 0xAB".to_string()),
+                (0x203, "db 0xCD".to_string()),
+                (0x204, "EXIT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_are_named_for_what_uses_them() {
+        let rom = [0xA2, 0x08, 0x22, 0x06, 0x00, 0xFD, 0x00, 0xEE, 0x3C];
+        let listing = disassemble(&rom, &Platform::default());
+        let labels: Vec<(u16, Option<&str>)> = listing
+            .iter()
+            .map(|line| (line.address, line.label.as_deref()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (0x200, Some("L200")),
+                (0x202, None),
+                (0x204, None),
+                (0x206, Some("L206")),
+                (0x208, Some("D208")),
+            ]
+        );
+        assert_eq!(listing[0].text, "LD I, D208");
+        assert_eq!(listing[1].text, "CALL L206");
+        assert_eq!(listing[4].comment.as_deref(), Some("..####.."));
+    }
+
+    #[test]
+    fn long_loads_take_four_bytes() {
+        let rom = [0xF0, 0x00, 0x02, 0x06, 0x00, 0xFD, 0xFF];
+        let listing = disassemble(&rom, &Platform::default());
+        assert_eq!(listing[0].bytes, [0xF0, 0x00, 0x02, 0x06]);
+        assert_eq!(listing[0].text, "LD I, long D206");
+        assert_eq!(listing[1].address, 0x204);
+        assert_eq!(listing[2].label.as_deref(), Some("D206"));
+    }
+
+    #[test]
+    fn skipping_a_long_load_skips_four_bytes() {
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x00, 0x00, 0x00, 0xFD];
+        assert_eq!(
+            lines(&rom),
+            [
+                (0x200, "SE V0, 0x00".to_string()),
+                (0x202, "LD I, long 0x000".to_string()),
+                (0x206, "EXIT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_inside_instructions_are_dropped() {
+        let rom = [0xA2, 0x01, 0x12, 0x03, 0x00, 0xFD];
+        let listing = disassemble(&rom, &Platform::default());
+        assert!(listing
+            .iter()
+            .all(|line| line.label.as_deref() != Some("D201")));
+        assert_eq!(listing[0].text, "LD I, 0x201");
+        assert_eq!(listing[1].text, "JP 0x203");
+    }
+
+    #[test]
+    fn the_largest_rom_reaches_the_end_of_memory() {
+        // LD V0, 0x00 all the way up to 0xFFFF, then a skip whose target is past the end.
+        let mut rom: Vec<u8> = [0x60, 0x00].repeat(MAX_ROM_SIZE / 2);
+        rom[MAX_ROM_SIZE - 2] = 0x30;
+        let listing = disassemble(&rom, &Platform::default());
+        assert_eq!(listing.len(), MAX_ROM_SIZE / 2);
+        let last = listing.last().unwrap();
+        assert_eq!((last.address, last.text.as_str()), (0xFFFE, "SE V0, 0x00"));
+        assert!(listing[0].label.is_some());
+        assert!(listing[1..].iter().all(|line| line.label.is_none()));
+    }
+
+    #[test]
+    fn programs_are_placed_at_the_platform_load_address() {
+        let rom = [0x16, 0x04, 0xAA, 0xBB, 0x00, 0xFD];
+        let listing = disassemble(&rom, &Platform::ETI_660);
+        assert_eq!(listing[0].address, 0x600);
+        assert_eq!(listing[0].text, "JP L604");
+        assert_eq!(listing[3].address, 0x604);
+        assert_eq!(listing[3].text, "EXIT");
+    }
+
+    #[test]
+    fn tracing_begins_at_the_platform_start_address() {
+        let mut rom = vec![0x12, 0x60];
+        rom.resize(0xC0, 0x0);
+        rom.extend([0x00, 0xFD]);
+        let listing = disassemble(&rom, &Platform::HIRES_VIP);
+        assert_eq!(listing[0].text, "db 0x12");
+        let start = listing.last().unwrap();
+        assert_eq!(
+            (start.address, start.label.as_deref(), start.text.as_str()),
+            (0x2C0, Some("L2C0"), "EXIT")
+        );
+    }
+}
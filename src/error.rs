@@ -0,0 +1,221 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why the processor could not execute an instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExecutionError {
+    /// 2nnn with all 16 stack entries in use.
+    StackOverflow { pc: u16 },
+    /// 00EE with an empty stack.
+    StackUnderflow { pc: u16 },
+    /// An instruction no supported platform defines.
+    InvalidOpcode { pc: u16, opcode: u16 },
+    /// A read or write past the end of memory.
+    MemoryOutOfBounds { pc: u16, addr: usize },
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutionError::StackOverflow { pc } => {
+                write!(f, "Stack overflow calling a subroutine at {:#06x}.", pc)
+            }
+            ExecutionError::StackUnderflow { pc } => {
+                write!(
+                    f,
+                    "Stack underflow returning from a subroutine at {:#06x}.",
+                    pc
+                )
+            }
+            ExecutionError::InvalidOpcode { pc, opcode } => {
+                write!(f, "Invalid opcode {:04X} at {:#06x}.", opcode, pc)
+            }
+            ExecutionError::MemoryOutOfBounds { pc, addr } => write!(
+                f,
+                "Memory access out of bounds at {:#x} by the instruction at {:#06x}.",
+                addr, pc
+            ),
+        }
+    }
+}
+
+impl Error for ExecutionError {}
+
+/// Why a save state could not be restored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StateError {
+    /// The data does not start with the save state header.
+    BadMagic,
+    /// The state was written by an incompatible version of the emulator.
+    UnsupportedVersion(u16),
+    /// The data ends before the state does.
+    Truncated,
+    /// The payload does not match its checksum.
+    ChecksumMismatch,
+    /// A field holds a value the processor cannot be in.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state."),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}.", version)
+            }
+            StateError::Truncated => write!(f, "The save state is truncated."),
+            StateError::ChecksumMismatch => write!(f, "The save state is corrupt."),
+            StateError::Invalid(field) => write!(f, "The save state has an invalid {}.", field),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Why a movie could not be played back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovieError {
+    /// The file is not a movie, or was written by an incompatible version of the emulator.
+    Unreadable(StateError),
+    /// The movie was recorded with a different ROM, identified by its CRC-32.
+    WrongRom { recorded: u32, loaded: u32 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Unreadable(StateError::BadMagic) => write!(f, "Not a movie."),
+            MovieError::Unreadable(StateError::UnsupportedVersion(version)) => {
+                write!(f, "Unsupported movie version {}.", version)
+            }
+            MovieError::Unreadable(_) => write!(f, "The movie is corrupt."),
+            MovieError::WrongRom { recorded, loaded } => write!(
+                f,
+                "The movie was recorded with a different ROM (CRC-32 {:08x}, not {:08x}).",
+                recorded, loaded
+            ),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(err: StateError) -> MovieError {
+        MovieError::Unreadable(err)
+    }
+}
+
+/// Why a config file could not be used.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The file is not valid TOML.
+    Syntax(String),
+    /// A setting that does not exist, probably misspelt.
+    UnknownSetting(String),
+    /// A setting with the wrong kind of value.
+    InvalidValue {
+        setting: String,
+        expected: &'static str,
+    },
+    /// One host key bound to two things.
+    Conflict {
+        key: String,
+        first: String,
+        second: String,
+    },
+    /// A host key the emulator keeps for itself.
+    ReservedKey { key: String, binding: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Syntax(message) => write!(f, "Invalid TOML: {}", message),
+            ConfigError::UnknownSetting(setting) => write!(f, "Unknown setting '{}'.", setting),
+            ConfigError::InvalidValue { setting, expected } => {
+                write!(f, "'{}' must be {}.", setting, expected)
+            }
+            ConfigError::Conflict { key, first, second } => write!(
+                f,
+                "The key '{}' is bound to both {} and {}.",
+                key, first, second
+            ),
+            ConfigError::ReservedKey { key, binding } => write!(
+                f,
+                "The key '{}' bound to {} is reserved for save slots.",
+                key, binding
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Why a program could not be assembled, and where.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssemblyError {
+    pub file: String,
+    /// 1-based, or 0 when the file itself could not be read.
+    pub line: usize,
+    /// 1-based.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// Why a ROM could not be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CartridgeError {
+    /// The file could not be read.
+    Io(String),
+    /// The file holds no program at all.
+    Empty,
+    /// The program does not fit in 64 KB of memory above 0x200.
+    TooLarge { size: usize, max: usize },
+    /// A GIF that could not be decoded or is not an Octo cartridge.
+    BadCartridge(String),
+    /// Octo source, on its own or from a cartridge, that does not compile.
+    Program(AssemblyError),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Io(message) => write!(f, "{}", message),
+            CartridgeError::Empty => write!(f, "The ROM is empty."),
+            CartridgeError::TooLarge { size, max } => write!(
+                f,
+                "The ROM is {} bytes, larger than the {} bytes of memory above 0x200.",
+                size, max
+            ),
+            CartridgeError::BadCartridge(message) => {
+                write!(f, "Not an Octo cartridge: {}.", message)
+            }
+            CartridgeError::Program(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for CartridgeError {}
+
+impl From<AssemblyError> for CartridgeError {
+    fn from(err: AssemblyError) -> CartridgeError {
+        CartridgeError::Program(err)
+    }
+}
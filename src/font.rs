@@ -0,0 +1,263 @@
+use crate::error::StateError;
+use crate::state::{StateReader, StateWriter};
+
+/// The 4x5 hexadecimal digits Fx29 points at, as different interpreters drew them. Some programs are laid out
+/// around the shapes of a particular one.
+/// The discriminants are stored in save states, so the order must not change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmallFont {
+    Chip48,     // The CHIP-48 and SUPER-CHIP font most modern interpreters copy, and the default.
+    CosmacVip,  // The original, with a different 1, 4 and 7.
+    Dream6800,  // Three pixels wide.
+    Eti660,     // Three pixels wide, with lower case b and d.
+    FishNChips, // Fish 'n' Chips, with rounded digits.
+}
+
+/// The 8x10 digits Fx30 points at.
+/// The discriminants are stored in save states, so the order must not change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BigFont {
+    Octo,      // Octo's blocky font with A to F, and the default.
+    SuperChip, // SUPER-CHIP 1.1's rounded font. It only has 0 to 9, so A to F are Octo's.
+}
+
+const CHIP_48: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const COSMAC_VIP: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const DREAM_6800: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const ETI_660: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // b
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // d
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const FISH_N_CHIPS: [u8; 80] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const OCTO_BIG: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+const SUPER_CHIP_BIG: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+impl SmallFont {
+    /// Looks up a font by name, as given on the command line.
+    pub fn from_name(name: &str) -> Option<SmallFont> {
+        match name.to_lowercase().as_str() {
+            "chip48" | "chip-48" | "schip" | "modern" => Some(SmallFont::Chip48),
+            "vip" | "cosmac-vip" => Some(SmallFont::CosmacVip),
+            "dream6800" | "dream-6800" => Some(SmallFont::Dream6800),
+            "eti660" | "eti-660" => Some(SmallFont::Eti660),
+            "fishnchips" | "fish-n-chips" => Some(SmallFont::FishNChips),
+            _ => None,
+        }
+    }
+
+    fn glyphs(self) -> &'static [u8; 80] {
+        match self {
+            SmallFont::Chip48 => &CHIP_48,
+            SmallFont::CosmacVip => &COSMAC_VIP,
+            SmallFont::Dream6800 => &DREAM_6800,
+            SmallFont::Eti660 => &ETI_660,
+            SmallFont::FishNChips => &FISH_N_CHIPS,
+        }
+    }
+}
+
+impl BigFont {
+    pub fn from_name(name: &str) -> Option<BigFont> {
+        match name.to_lowercase().as_str() {
+            "octo" | "xochip" | "xo-chip" => Some(BigFont::Octo),
+            "schip" | "super-chip" | "superchip" => Some(BigFont::SuperChip),
+            _ => None,
+        }
+    }
+
+    fn glyphs(self) -> [u8; 160] {
+        let mut glyphs = OCTO_BIG;
+        if self == BigFont::SuperChip {
+            glyphs[..100].copy_from_slice(&SUPER_CHIP_BIG);
+        }
+        glyphs
+    }
+}
+
+/// The fonts in use and where they are in memory: the small font at the address, followed by the big one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fonts {
+    pub small: SmallFont,
+    pub big: BigFont,
+    pub address: u16,
+}
+
+impl Fonts {
+    /// The bytes both fonts take up.
+    pub const SIZE: usize = 80 + 160;
+
+    /// Where the small sprite for the low nibble of a digit is.
+    pub fn digit(&self, digit: u8) -> u16 {
+        self.address + (digit & 0xf) as u16 * 5
+    }
+
+    /// Where the big sprite for the low nibble of a digit is.
+    pub fn big_digit(&self, digit: u8) -> u16 {
+        self.address + 80 + (digit & 0xf) as u16 * 10
+    }
+
+    /// Copies both fonts into memory at the address.
+    pub fn load_into(&self, memory: &mut [u8]) {
+        let address = self.address as usize;
+        memory[address..address + 80].copy_from_slice(self.small.glyphs());
+        memory[address + 80..address + Fonts::SIZE].copy_from_slice(&self.big.glyphs());
+    }
+
+    /// Writes the fonts in the form read by read_from.
+    pub fn write_to(&self, state: &mut StateWriter) {
+        state.put_u8(self.small as u8);
+        state.put_u8(self.big as u8);
+        state.put_u16(self.address);
+    }
+
+    pub fn read_from(state: &mut StateReader) -> Result<Fonts, StateError> {
+        let small = match state.get_u8()? {
+            0 => SmallFont::Chip48,
+            1 => SmallFont::CosmacVip,
+            2 => SmallFont::Dream6800,
+            3 => SmallFont::Eti660,
+            4 => SmallFont::FishNChips,
+            _ => return Err(StateError::Invalid("font")),
+        };
+        let big = match state.get_u8()? {
+            0 => BigFont::Octo,
+            1 => BigFont::SuperChip,
+            _ => return Err(StateError::Invalid("font")),
+        };
+        let address = state.get_u16()?;
+        if address as usize + Fonts::SIZE > 0x10000 {
+            return Err(StateError::Invalid("font address"));
+        }
+        Ok(Fonts {
+            small,
+            big,
+            address,
+        })
+    }
+}
+
+impl Default for Fonts {
+    fn default() -> Fonts {
+        Fonts {
+            small: SmallFont::Chip48,
+            big: BigFont::Octo,
+            address: 0x50,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Colour of each combination of the two XO-CHIP bitplanes, as RGB.
+pub const PALETTE: [(u8, u8, u8); 4] = [(0, 0, 0), (255, 255, 255), (170, 170, 170), (85, 85, 85)];
+
+/// A display whose resolution depends on the current video mode.
+/// Each pixel holds one bit per XO-CHIP bitplane, giving up to four colours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>, // Row major, one byte per pixel.
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0x0; width * height],
+        }
+    }
+
+    /// Rebuilds a framebuffer from the row major pixels returned by pixels().
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<u8>) -> Option<Framebuffer> {
+        if pixels.len() != width * height {
+            return None;
+        }
+        Some(Framebuffer {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: u8) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    /// Clears the bitplanes selected by mask.
+    pub fn clear(&mut self, mask: u8) {
+        self.pixels.iter_mut().for_each(|pixel| *pixel &= !mask);
+    }
+
+    /// Moves the bitplanes selected by mask down by n pixels, filling the top with blank rows.
+    pub fn scroll_down(&mut self, n: usize, mask: u8) {
+        let n = n.min(self.height);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let source = if y >= n { self.get(x, y - n) } else { 0x0 };
+                self.blend(x, y, source, mask);
+            }
+        }
+    }
+
+    /// Moves the bitplanes selected by mask up by n pixels, filling the bottom with blank rows.
+    pub fn scroll_up(&mut self, n: usize, mask: u8) {
+        let n = n.min(self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let source = if y + n < self.height {
+                    self.get(x, y + n)
+                } else {
+                    0x0
+                };
+                self.blend(x, y, source, mask);
+            }
+        }
+    }
+
+    /// Moves the bitplanes selected by mask right by n pixels, filling the left with blank columns.
+    pub fn scroll_right(&mut self, n: usize, mask: u8) {
+        let n = n.min(self.width);
+        for y in 0..self.height {
+            for x in (0..self.width).rev() {
+                let source = if x >= n { self.get(x - n, y) } else { 0x0 };
+                self.blend(x, y, source, mask);
+            }
+        }
+    }
+
+    /// Moves the bitplanes selected by mask left by n pixels, filling the right with blank columns.
+    pub fn scroll_left(&mut self, n: usize, mask: u8) {
+        let n = n.min(self.width);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let source = if x + n < self.width {
+                    self.get(x + n, y)
+                } else {
+                    0x0
+                };
+                self.blend(x, y, source, mask);
+            }
+        }
+    }
+
+    /// Replaces the bits selected by mask at (x, y) with those of source.
+    fn blend(&mut self, x: usize, y: usize, source: u8, mask: u8) {
+        let pixel = self.get(x, y);
+        self.set(x, y, (pixel & !mask) | (source & mask));
+    }
+}
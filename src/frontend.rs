@@ -0,0 +1,32 @@
+use crate::audio::Sound;
+use crate::framebuffer::Framebuffer;
+use std::time::Duration;
+
+/// What the user asked for since the last poll.
+pub enum KeyboardInput {
+    Quit,
+    Input([bool; 16]), // Pressed state of the 16 CHIP-8 keys, 0x0 to 0xF.
+    SaveState(u8),     // Save to the numbered slot.
+    LoadState(u8),     // Restore from the numbered slot.
+    Rewind,            // Step back one frame instead of running the next.
+    Pause,             // Stop running, or carry on if already stopped.
+    Reset,             // Restart the ROM.
+    SpeedUp,
+    SpeedDown,
+}
+
+/// Somewhere to show the processor's framebuffer.
+pub trait Display {
+    fn update(&mut self, frame: &Framebuffer);
+}
+
+/// A source of CHIP-8 key presses.
+pub trait Input {
+    fn handle_input(&mut self) -> KeyboardInput;
+}
+
+/// Somewhere to send the processor's sound.
+pub trait Audio {
+    /// Outputs sound for the next duration of emulated time.
+    fn play(&mut self, sound: Sound, duration: Duration);
+}
@@ -0,0 +1,266 @@
+use crate::error::ExecutionError;
+use crate::movie::Session;
+use crate::processor::Processor;
+use crate::scheduler::Scheduler;
+use crate::timing::Timing;
+use std::fmt;
+
+/// Keys held down from given frames on, for running without a keyboard.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyScript {
+    events: Vec<(u32, [bool; 16])>, // Frame numbers in ascending order.
+}
+
+impl KeyScript {
+    /// Parses events of the form frame:keys, separated by commas or whitespace. Keys are hex digits, and an empty
+    /// set releases everything. For example "60:5 62: 120:46" taps 5 at one second, then holds 4 and 6.
+    pub fn parse(text: &str) -> Result<KeyScript, String> {
+        let mut events = Vec::new();
+        for event in text.split(|c: char| c == ',' || c.is_whitespace()) {
+            if event.is_empty() {
+                continue;
+            }
+            let (frame, keys) = event
+                .split_once(':')
+                .ok_or_else(|| format!("Key event '{}' is not frame:keys.", event))?;
+            let frame: u32 = frame
+                .parse()
+                .map_err(|_| format!("Invalid frame number in '{}'.", event))?;
+            let mut held = [false; 16];
+            for key in keys.chars() {
+                let key = key
+                    .to_digit(16)
+                    .ok_or_else(|| format!("Invalid key '{}' in '{}'.", key, event))?;
+                held[key as usize] = true;
+            }
+            events.push((frame, held));
+        }
+        events.sort_by_key(|&(frame, _)| frame);
+        Ok(KeyScript { events })
+    }
+
+    /// The keys held during a frame.
+    pub fn keys_at(&self, frame: u32) -> [bool; 16] {
+        self.events
+            .iter()
+            .take_while(|&&(start, _)| start <= frame)
+            .last()
+            .map_or([false; 16], |&(_, keys)| keys)
+    }
+}
+
+/// When to stop running without a window. Execution also stops when the program exits with 00FD.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessSettings {
+    pub cpu_hz: u32,
+    pub timing: Timing,
+    pub max_frames: Option<u32>,
+    pub stop_at: Option<u16>, // Stop before executing the instruction at this address.
+    pub stop_on_loop: bool,   // Stop at a jump to itself, the usual way for a test ROM to finish.
+    pub keys: KeyScript,
+}
+
+/// Why a headless run ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    FrameLimit,
+    ReachedPc(u16),
+    Loop(u16),
+    Exited,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::FrameLimit => write!(f, "reached the frame limit"),
+            StopReason::ReachedPc(pc) => write!(f, "reached {:#06x}", pc),
+            StopReason::Loop(pc) => write!(f, "looping at {:#06x}", pc),
+            StopReason::Exited => write!(f, "the program exited"),
+        }
+    }
+}
+
+/// Runs as fast as possible in 60 Hz frames until a stop condition is met, returning it and the number of frames
+/// that ran.
+pub fn run(
+    cpu: &mut Processor,
+    settings: &HeadlessSettings,
+) -> Result<(StopReason, u32), ExecutionError> {
+    run_movie(cpu, settings, None)
+}
+
+/// Runs like run while recording or playing back a movie. Played back keys replace the key script.
+pub fn run_movie(
+    cpu: &mut Processor,
+    settings: &HeadlessSettings,
+    mut movie: Option<&mut Session>,
+) -> Result<(StopReason, u32), ExecutionError> {
+    let mut scheduler = Scheduler::new(settings.cpu_hz, settings.timing);
+    let mut frame = 0;
+    loop {
+        if settings.max_frames.is_some_and(|max| frame >= max) {
+            return Ok((StopReason::FrameLimit, frame));
+        }
+        let mut keys = settings.keys.keys_at(frame);
+        if let Some(movie) = movie.as_deref() {
+            keys = movie.keys(frame, keys);
+        }
+        scheduler.start_frame();
+        while scheduler.frame_has_time() {
+            let pc = cpu.pc();
+            if settings.stop_at == Some(pc) {
+                return Ok((StopReason::ReachedPc(pc), frame));
+            }
+            if settings.stop_on_loop && jumps_to_itself(cpu, pc) {
+                return Ok((StopReason::Loop(pc), frame));
+            }
+            let result = cpu.cycle(keys)?;
+            if result.exited {
+                if let Some(movie) = movie.as_deref_mut() {
+                    movie.end_frame(frame, keys, cpu.framebuffer());
+                }
+                return Ok((StopReason::Exited, frame + 1));
+            }
+            scheduler.spend(&result);
+        }
+        cpu.tick_timers();
+        if let Some(movie) = movie.as_deref_mut() {
+            movie.end_frame(frame, keys, cpu.framebuffer());
+        }
+        frame += 1;
+    }
+}
+
+/// Whether the instruction at pc is a 1nnn jump to pc, which can only be the case below 0x1000.
+fn jumps_to_itself(cpu: &Processor, pc: u16) -> bool {
+    pc <= 0xFFF && opcode_at(cpu, pc) == Some(0x1000 | pc)
+}
+
+fn opcode_at(cpu: &Processor, addr: u16) -> Option<u16> {
+    let memory = cpu.memory();
+    let high = *memory.get(addr as usize)?;
+    let low = *memory.get(addr as usize + 1)?;
+    Some((high as u16) << 8 | low as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+    use crate::quirks::Quirks;
+
+    fn settings(keys: &str) -> HeadlessSettings {
+        HeadlessSettings {
+            cpu_hz: 600,
+            timing: Timing::Uniform,
+            max_frames: Some(10),
+            stop_at: None,
+            stop_on_loop: true,
+            keys: KeyScript::parse(keys).unwrap(),
+        }
+    }
+
+    fn boot(rom: &[u8]) -> Processor {
+        let mut cpu = Processor::new(Quirks::MODERN);
+        cpu.load_cartridge(rom);
+        cpu
+    }
+
+    #[test]
+    fn key_scripts_hold_keys_until_the_next_event() {
+        let script = KeyScript::parse("120:46, 60:5\t62:").unwrap();
+        let mut held = [false; 16];
+        assert_eq!(script.keys_at(59), held);
+        held[5] = true;
+        assert_eq!(script.keys_at(60), held);
+        assert_eq!(script.keys_at(61), held);
+        assert_eq!(script.keys_at(62), [false; 16]);
+        held = [false; 16];
+        held[4] = true;
+        held[6] = true;
+        assert_eq!(script.keys_at(1000), held);
+        assert_eq!(KeyScript::parse(""), Ok(KeyScript::default()));
+    }
+
+    #[test]
+    fn malformed_key_scripts_are_rejected() {
+        assert_eq!(
+            KeyScript::parse("60"),
+            Err("Key event '60' is not frame:keys.".to_string())
+        );
+        assert_eq!(
+            KeyScript::parse("x:5"),
+            Err("Invalid frame number in 'x:5'.".to_string())
+        );
+        assert_eq!(
+            KeyScript::parse("-1:5"),
+            Err("Invalid frame number in '-1:5'.".to_string())
+        );
+        assert_eq!(
+            KeyScript::parse("60:5g"),
+            Err("Invalid key 'g' in '60:5g'.".to_string())
+        );
+    }
+
+    #[test]
+    fn runs_stop_at_loops_and_addresses() {
+        let rom = [0x60, 0x01, 0x12, 0x02];
+        let mut cpu = boot(&rom);
+        assert_eq!(
+            run(&mut cpu, &settings("")),
+            Ok((StopReason::Loop(0x202), 0))
+        );
+
+        let mut cpu = boot(&rom);
+        let stop_at = HeadlessSettings {
+            stop_at: Some(0x202),
+            stop_on_loop: false,
+            ..settings("")
+        };
+        assert_eq!(
+            run(&mut cpu, &stop_at),
+            Ok((StopReason::ReachedPc(0x202), 0))
+        );
+        assert_eq!(cpu.v()[0], 0x1);
+    }
+
+    #[test]
+    fn runs_stop_when_the_program_exits() {
+        let mut cpu = boot(&[0x00, 0xFD]);
+        assert_eq!(run(&mut cpu, &settings("")), Ok((StopReason::Exited, 1)));
+    }
+
+    #[test]
+    fn runs_stop_at_the_frame_limit() {
+        let mut cpu = boot(&[0x12, 0x02, 0x12, 0x00]);
+        assert_eq!(
+            run(&mut cpu, &settings("")),
+            Ok((StopReason::FrameLimit, 10))
+        );
+    }
+
+    #[test]
+    fn jumps_above_0xfff_are_not_loops() {
+        // Runs up to 0x1200, where a jump back to 0x200 looks like a jump to itself if the top of the address is
+        // ignored.
+        let mut rom = [0x60, 0x00].repeat(0x800);
+        rom.extend_from_slice(&[0x12, 0x00]);
+        let mut cpu = Processor::for_platform(Quirks::MODERN, Platform::XO_CHIP);
+        cpu.load_cartridge(&rom);
+        let fast = HeadlessSettings {
+            cpu_hz: 1000 * 60,
+            ..settings("")
+        };
+        assert_eq!(run(&mut cpu, &fast), Ok((StopReason::FrameLimit, 10)));
+    }
+
+    #[test]
+    fn key_scripts_press_keys() {
+        // Loops until key 0 is held, then stops at the jump to itself at 0x204.
+        let mut cpu = boot(&[0xE0, 0x9E, 0x12, 0x00, 0x12, 0x04]);
+        assert_eq!(
+            run(&mut cpu, &settings("5:0")),
+            Ok((StopReason::Loop(0x204), 5))
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::framebuffer::{Framebuffer, PALETTE};
+use std::path::Path;
+
+/// The file formats a framebuffer can be saved in, one image pixel per display pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,   // Indexed colour, using the display palette.
+    Pbm,   // Plain black and white, lit wherever any bitplane is set.
+    Ascii, // One character per pixel: . for unlit, then #, + and * for the XO-CHIP colours.
+}
+
+impl ImageFormat {
+    /// Picks the format from a file extension, falling back to ASCII art.
+    pub fn from_path(path: &Path) -> ImageFormat {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("png") => ImageFormat::Png,
+            Some("pbm") => ImageFormat::Pbm,
+            _ => ImageFormat::Ascii,
+        }
+    }
+}
+
+pub fn encode(frame: &Framebuffer, format: ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Png => png(frame),
+        ImageFormat::Pbm => pbm(frame),
+        ImageFormat::Ascii => ascii(frame).into_bytes(),
+    }
+}
+
+pub fn ascii(frame: &Framebuffer) -> String {
+    let mut text = String::new();
+    for y in 0..frame.height() {
+        for x in 0..frame.width() {
+            text.push(['.', '#', '+', '*'][frame.get(x, y) as usize & 0x3]);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// A plain (P1) portable bitmap.
+pub fn pbm(frame: &Framebuffer) -> Vec<u8> {
+    let mut text = format!("P1\n{} {}\n", frame.width(), frame.height());
+    for y in 0..frame.height() {
+        let row: Vec<&str> = (0..frame.width())
+            .map(|x| if frame.get(x, y) != 0x0 { "1" } else { "0" })
+            .collect();
+        text.push_str(&row.join(" "));
+        text.push('\n');
+    }
+    text.into_bytes()
+}
+
+/// An 8-bit indexed colour PNG.
+pub fn png(frame: &Framebuffer) -> Vec<u8> {
+    let palette: Vec<u8> = PALETTE
+        .iter()
+        .flat_map(|&(r, g, b)| vec![r, g, b])
+        .collect();
+    let mut pixels = Vec::new();
+    for y in 0..frame.height() {
+        pixels.extend((0..frame.width()).map(|x| frame.get(x, y) & 0x3));
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, frame.width() as u32, frame.height() as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette);
+    // Writing to memory cannot fail, and the header matches the data.
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&pixels).unwrap();
+    writer.finish().unwrap();
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pngs_hold_the_colour_indices() {
+        let mut frame = Framebuffer::new(64, 32);
+        frame.set(1, 0, 0x1);
+        frame.set(63, 31, 0x3);
+        let image = png(&frame);
+        let mut reader = png::Decoder::new(&image[..]).read_info().unwrap();
+        let mut pixels = vec![0x0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (64, 32));
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert_eq!(&pixels[..3], &[0x0, 0x1, 0x0]);
+        assert_eq!(pixels[64 * 32 - 1], 0x3);
+        assert_eq!(
+            reader.info().palette.as_deref().map(<[u8]>::len),
+            Some(PALETTE.len() * 3)
+        );
+    }
+}
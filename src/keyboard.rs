@@ -1,69 +0,0 @@
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-
-pub enum KeyboardInput {
-    Quit,
-    Input([bool; 16]), // Temporary place holder until keyboard input can be handled.
-}
-
-pub struct Keyboard {
-    event_pump: sdl2::EventPump,
-}
-
-impl Keyboard {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Keyboard {
-        let event_pump = sdl_context.event_pump().unwrap();
-        Keyboard {
-            event_pump: event_pump,
-        }
-    }
-    pub fn handle_input(&mut self) -> KeyboardInput {
-        for event in self.event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => return KeyboardInput::Quit,
-                _ => (),
-            }
-        }
-
-        let keys: Vec<Keycode> = self
-            .event_pump
-            .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
-
-        let mut key_codes = [false; 16];
-
-        for key in keys {
-            let index = match key {
-                Keycode::Num1 => Some(0x1),
-                Keycode::Num2 => Some(0x2),
-                Keycode::Num3 => Some(0x3),
-                Keycode::Num4 => Some(0xc),
-                Keycode::Q => Some(0x4),
-                Keycode::W => Some(0x5),
-                Keycode::E => Some(0x6),
-                Keycode::R => Some(0xd),
-                Keycode::A => Some(0x7),
-                Keycode::S => Some(0x8),
-                Keycode::D => Some(0x9),
-                Keycode::F => Some(0xe),
-                Keycode::Z => Some(0xa),
-                Keycode::X => Some(0x0),
-                Keycode::C => Some(0xb),
-                Keycode::V => Some(0xf),
-                _ => None,
-            };
-
-            if let Some(i) = index {
-                key_codes[i] = true;
-            }
-        }
-
-        KeyboardInput::Input(key_codes)
-    }
-}
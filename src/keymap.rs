@@ -0,0 +1,281 @@
+use crate::error::ConfigError;
+use std::fmt;
+
+/// Emulator controls that can be bound to keys alongside the CHIP-8 keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    Quit,
+    Pause,     // Stops and restarts emulation.
+    Reset,     // Restarts the ROM from scratch.
+    SaveState, // Saves to slot 0. Shift + F1 to F9 save to the other slots.
+    LoadState, // Restores slot 0. F1 to F9 restore the other slots.
+    Rewind,    // Held to run backwards.
+    SpeedUp,   // Doubles the instructions per second.
+    SpeedDown, // Halves the instructions per second.
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 8] = [
+        Hotkey::Quit,
+        Hotkey::Pause,
+        Hotkey::Reset,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::Rewind,
+        Hotkey::SpeedUp,
+        Hotkey::SpeedDown,
+    ];
+
+    /// The name used in config files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::Quit => "quit",
+            Hotkey::Pause => "pause",
+            Hotkey::Reset => "reset",
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+            Hotkey::Rewind => "rewind",
+            Hotkey::SpeedUp => "speed_up",
+            Hotkey::SpeedDown => "speed_down",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Hotkey> {
+        Hotkey::ALL
+            .iter()
+            .copied()
+            .find(|hotkey| hotkey.name() == name)
+    }
+}
+
+/// What a host key does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Keypad(u8), // A CHIP-8 key, 0x0 to 0xF.
+    Hotkey(Hotkey),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Keypad(key) => write!(f, "keypad {:X}", key),
+            Binding::Hotkey(hotkey) => write!(f, "{}", hotkey.name()),
+        }
+    }
+}
+
+/// Keyboard layouts with a preset that puts the keypad on the same physical keys: the 4x4 block from 1 down to V
+/// on QWERTY.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    Qwerty,
+    Azerty,
+    Dvorak,
+}
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name.to_lowercase().as_str() {
+            "qwerty" => Some(Layout::Qwerty),
+            "azerty" => Some(Layout::Azerty),
+            "dvorak" => Some(Layout::Dvorak),
+            _ => None,
+        }
+    }
+
+    /// Host key names for the keypad, row by row as it is laid out: 123C, 456D, 789E, A0BF.
+    fn keys(self) -> [&'static str; 16] {
+        match self {
+            Layout::Qwerty => [
+                "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V",
+            ],
+            Layout::Azerty => [
+                "1", "2", "3", "4", "A", "Z", "E", "R", "Q", "S", "D", "F", "W", "X", "C", "V",
+            ],
+            Layout::Dvorak => [
+                "1", "2", "3", "4", "'", ",", ".", "P", "A", "O", "E", "U", ";", "Q", "J", "K",
+            ],
+        }
+    }
+}
+
+// The CHIP-8 key at each position of the keypad, row by row.
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+// Save slots 1 to 9 always use the function keys.
+const SLOT_KEYS: [&str; 9] = ["F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9"];
+
+/// Which host keys, by name, press each CHIP-8 key and hotkey. Key names are the ones SDL uses, e.g. "Q",
+/// "Space" or "Left Shift", and are compared without regard to case.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyMap {
+    keypad: [Vec<String>; 16],
+    hotkeys: Vec<(Hotkey, Vec<String>)>,
+}
+
+impl KeyMap {
+    /// The keypad for a layout, with the default hotkeys.
+    pub fn preset(layout: Layout) -> KeyMap {
+        let mut keymap = KeyMap {
+            keypad: Default::default(),
+            hotkeys: vec![
+                (Hotkey::Quit, vec!["Escape".to_string()]),
+                (Hotkey::Pause, vec!["Space".to_string()]),
+                (Hotkey::Reset, vec!["F12".to_string()]),
+                (Hotkey::SaveState, vec!["F10".to_string()]),
+                (Hotkey::LoadState, vec!["F11".to_string()]),
+                (Hotkey::Rewind, vec!["Backspace".to_string()]),
+                (Hotkey::SpeedUp, vec!["=".to_string()]),
+                (Hotkey::SpeedDown, vec!["-".to_string()]),
+            ],
+        };
+        keymap.set_layout(layout);
+        keymap
+    }
+
+    /// Rebinds the whole keypad to a layout's preset.
+    pub fn set_layout(&mut self, layout: Layout) {
+        for (&key, name) in KEYPAD.iter().zip(layout.keys().iter()) {
+            self.keypad[key as usize] = vec![name.to_string()];
+        }
+    }
+
+    /// Replaces the host keys for a binding.
+    pub fn bind(&mut self, binding: Binding, keys: Vec<String>) {
+        match binding {
+            Binding::Keypad(key) => self.keypad[key as usize & 0xf] = keys,
+            Binding::Hotkey(hotkey) => {
+                for (bound, bound_keys) in self.hotkeys.iter_mut() {
+                    if *bound == hotkey {
+                        *bound_keys = keys;
+                        return;
+                    }
+                }
+                self.hotkeys.push((hotkey, keys));
+            }
+        }
+    }
+
+    /// Every host key with what it does.
+    pub fn bindings(&self) -> Vec<(&str, Binding)> {
+        let keypad = self.keypad.iter().enumerate().flat_map(|(key, names)| {
+            names
+                .iter()
+                .map(move |name| (name.as_str(), Binding::Keypad(key as u8)))
+        });
+        let hotkeys = self.hotkeys.iter().flat_map(|(hotkey, names)| {
+            names
+                .iter()
+                .map(move |name| (name.as_str(), Binding::Hotkey(*hotkey)))
+        });
+        keypad.chain(hotkeys).collect()
+    }
+
+    /// Checks that no host key does two things, including the function keys kept for save slots.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let bindings = self.bindings();
+        for (i, &(name, binding)) in bindings.iter().enumerate() {
+            if SLOT_KEYS.iter().any(|slot| slot.eq_ignore_ascii_case(name)) {
+                return Err(ConfigError::ReservedKey {
+                    key: name.to_string(),
+                    binding: binding.to_string(),
+                });
+            }
+            let other = bindings[i + 1..]
+                .iter()
+                .find(|(other, _)| other.eq_ignore_ascii_case(name));
+            if let Some(&(_, other)) = other {
+                return Err(ConfigError::Conflict {
+                    key: name.to_string(),
+                    first: binding.to_string(),
+                    second: other.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::preset(Layout::Qwerty)
+    }
+}
+
+/// Game controller buttons and stick directions, named as in SDL's controller mappings. Axes take + or - for
+/// the direction, except the triggers which only go one way.
+pub const CONTROLLER_INPUTS: [&str; 25] = [
+    "a",
+    "b",
+    "x",
+    "y",
+    "back",
+    "guide",
+    "start",
+    "leftstick",
+    "rightstick",
+    "leftshoulder",
+    "rightshoulder",
+    "dpup",
+    "dpdown",
+    "dpleft",
+    "dpright",
+    "leftx-",
+    "leftx+",
+    "lefty-",
+    "lefty+",
+    "rightx-",
+    "rightx+",
+    "righty-",
+    "righty+",
+    "lefttrigger",
+    "righttrigger",
+];
+
+/// Which CHIP-8 key each game controller button or stick direction presses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControllerMap {
+    pub deadzone: f32, // The fraction of a stick's travel around the centre that is ignored.
+    bindings: Vec<(String, u8)>,
+}
+
+impl ControllerMap {
+    /// Replaces what a controller input presses, or unbinds it given None.
+    pub fn bind(&mut self, input: &str, key: Option<u8>) {
+        self.bindings.retain(|(bound, _)| bound != input);
+        if let Some(key) = key {
+            self.bindings.push((input.to_string(), key & 0xf));
+        }
+    }
+
+    pub fn bindings(&self) -> &[(String, u8)] {
+        &self.bindings
+    }
+}
+
+impl Default for ControllerMap {
+    /// The D-pad and left stick press 5, 7, 8 and 9 like WASD does on the keyboard, A presses 6 and B presses 4.
+    fn default() -> ControllerMap {
+        let mut map = ControllerMap {
+            deadzone: 0.25,
+            bindings: Vec::new(),
+        };
+        for (input, key) in [
+            ("dpup", 0x5),
+            ("dpleft", 0x7),
+            ("dpdown", 0x8),
+            ("dpright", 0x9),
+            ("lefty-", 0x5),
+            ("leftx-", 0x7),
+            ("lefty+", 0x8),
+            ("leftx+", 0x9),
+            ("a", 0x6),
+            ("b", 0x4),
+        ] {
+            map.bind(input, Some(key));
+        }
+        map
+    }
+}
@@ -0,0 +1,31 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP emulator core.
+//!
+//! The core has no dependency on any windowing or audio library. Frontends implement the traits in `frontend`;
+//! SDL2 ones are available with the `sdl` feature.
+
+pub mod asm;
+pub mod audio;
+pub mod cartridge;
+pub mod config;
+pub mod database;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod font;
+pub mod framebuffer;
+pub mod frontend;
+pub mod headless;
+pub mod image;
+pub mod keymap;
+pub mod movie;
+pub mod octo;
+pub mod platform;
+pub mod processor;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod scheduler;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod state;
+pub mod timing;
@@ -1,45 +1,480 @@
-extern crate sdl2;
+mod options;
 
-mod cartridge;
-mod keyboard;
-mod processor;
-mod video;
-
-use keyboard::Keyboard;
-use keyboard::KeyboardInput;
-use processor::Processor;
+use chip_8::asm;
+#[cfg(feature = "sdl")]
+use chip_8::audio::{NullAudio, Sound, WavAudio};
+use chip_8::cartridge::Cartridge;
+#[cfg(feature = "sdl")]
+use chip_8::config::Config;
+use chip_8::database::RomInfo;
+#[cfg(feature = "sdl")]
+use chip_8::debugger::{DebugAction, Debugger};
+use chip_8::disasm;
+use chip_8::error::{CartridgeError, ExecutionError};
+#[cfg(feature = "sdl")]
+use chip_8::framebuffer::PALETTE;
+#[cfg(feature = "sdl")]
+use chip_8::frontend::{Audio, Display, Input, KeyboardInput};
+use chip_8::headless::{self, HeadlessSettings};
+use chip_8::image::{self, ImageFormat};
+use chip_8::movie::{Movie, Session};
+use chip_8::octo;
+use chip_8::processor::Processor;
+use chip_8::random::Random;
+#[cfg(feature = "sdl")]
+use chip_8::rewind::Rewind;
+#[cfg(feature = "sdl")]
+use chip_8::scheduler::{Scheduler, FRAME_RATE};
+#[cfg(feature = "sdl")]
+use chip_8::sdl::{Controllers, Keyboard, SdlAudio, Video};
+#[cfg(feature = "sdl")]
+use chip_8::timing::Timing;
+#[cfg(feature = "sdl")]
+use options::AudioOutput;
+use options::{Options, ToolOptions};
 use std::env;
-use std::thread::sleep;
+use std::fs;
+#[cfg(feature = "sdl")]
+use std::io;
+use std::path::Path;
+#[cfg(feature = "sdl")]
 use std::time::Duration;
-use video::Video;
 
-fn main() -> Result<(), &'static str> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        return Err("A path to a valid *.ch8 file must be provided.");
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("asm") => return assemble(&args[1..]),
+        Some("disasm") => return disassemble(&args[1..]),
+        Some("octo") => return compile_octo(&args[1..]),
+        _ => (),
+    }
+    let mut options = Options::parse(&args)?;
+
+    let cartridge = load_cartridge(&options.rom_path, options.platform.load_address)?;
+    if !cartridge.fits_platform() {
+        eprintln!(
+            "Warning: the ROM is {} bytes, more than the {} bytes a {} program has room for.",
+            cartridge.size(),
+            cartridge.format().max_rom_size(),
+            cartridge.format().name()
+        );
+    } else {
+        options.suggest_platform(cartridge.format().platform());
+    }
+    let rom_info = if options.use_database {
+        RomInfo::lookup(&cartridge.sha1_hex())
+    } else {
+        None
+    };
+    if let Some(info) = &rom_info {
+        println!("Recognised {}.", describe(info));
+        options.apply_rom_info(info);
+    }
+    let mut movie = match options.play_path.clone() {
+        Some(path) => Some(start_playback(&path, &mut options, cartridge.rom())?),
+        None => None,
+    };
+    options.check_layout(cartridge.size())?;
+    let seed = options.seed.unwrap_or_else(rand::random);
+    println!("Random seed: {}", seed);
+    options.seed = Some(seed);
+    if options.record_path.is_some() {
+        movie = Some(Session::Recording(Movie::new(
+            cartridge.rom(),
+            seed,
+            options.quirks,
+            options.fonts,
+            options.platform,
+            options.timing,
+            options.cpu_hz,
+        )));
+    }
+    let mut cpu = boot(&options, &cartridge);
+    let rpl_path = format!("{}.rpl", options.rom_path);
+    let saved_flags = load_rpl_flags(&rpl_path).unwrap_or([0x0; 16]);
+    cpu.set_rpl_flags(saved_flags);
+
+    // A crash still leaves the RPL flags and the movie so far to save.
+    let run = match &options.headless {
+        Some(settings) => run_headless(
+            settings,
+            options.dump_path.as_deref(),
+            &mut cpu,
+            movie.as_mut(),
+        ),
+        None => run_window(
+            &options,
+            &cartridge,
+            rom_info.as_ref(),
+            &mut cpu,
+            movie.as_mut(),
+        ),
+    };
+
+    if cpu.rpl_flags() != saved_flags {
+        fs::write(&rpl_path, cpu.rpl_flags()).expect("Unable to save RPL flags.");
+    }
+
+    let finished = match movie {
+        Some(movie) => finish_movie(&options, movie),
+        None => Ok(()),
+    };
+    run.and(finished)
+}
+
+/// A ROM's title, authors and the settings the database has for it, e.g. "Pong by Paul Vervalin (modernChip8,
+/// 15 instructions per frame)".
+fn describe(info: &RomInfo) -> String {
+    let mut description = info.title.clone();
+    if !info.authors.is_empty() {
+        description += &format!(" by {}", info.authors.join(", "));
+    }
+    let settings: Vec<String> = info
+        .platform
+        .clone()
+        .into_iter()
+        .chain(
+            info.ipf
+                .map(|ipf| format!("{} instructions per frame", ipf)),
+        )
+        .collect();
+    if !settings.is_empty() {
+        description += &format!(" ({})", settings.join(", "));
     }
+    description
+}
 
-    let filename = &args[1];
+/// A processor with the ROM loaded, ready to run it from the start.
+fn boot(options: &Options, cartridge: &Cartridge) -> Processor {
+    let mut cpu = Processor::for_platform(options.quirks, options.platform);
+    cpu.set_fonts(options.fonts);
+    cpu.load_cartridge(cartridge.rom());
+    let seed = options.seed.unwrap_or_default();
+    cpu.set_random(Random::new(seed));
+    cpu
+}
 
-    let cartridge = cartridge::load_from_file(filename).expect("Unable to load catridge.");
-    let mut cpu = Processor::new();
-    cpu.load_cartridge(&cartridge);
+/// Loads a movie to play back and takes on the settings it was recorded with.
+fn start_playback(path: &str, options: &mut Options, rom: &[u8]) -> Result<Session, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let movie = Movie::decode(&data).map_err(|e| format!("{}: {}", path, e))?;
+    movie
+        .check_rom(rom)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    options.quirks = movie.quirks;
+    options.fonts = movie.fonts;
+    options.platform = movie.platform;
+    options.timing = movie.timing;
+    options.seed = Some(movie.seed);
+    options.cpu_hz = movie.cpu_hz;
+    if let Some(settings) = options.headless.as_mut() {
+        settings.cpu_hz = movie.cpu_hz;
+        settings.timing = movie.timing;
+        settings.max_frames = Some(settings.max_frames.unwrap_or(u32::MAX).min(movie.len()));
+    }
+    Ok(Session::Playing {
+        movie,
+        diverged_at: None,
+    })
+}
 
+/// Saves a recorded movie, or reports how playback went.
+fn finish_movie(options: &Options, movie: Session) -> Result<(), String> {
+    match movie {
+        Session::Recording(movie) => {
+            let path = options.record_path.as_deref().unwrap_or_default();
+            fs::write(path, movie.encode()).map_err(|e| format!("{}: {}", path, e))?;
+            println!("Recorded {} frames to {}.", movie.len(), path);
+            Ok(())
+        }
+        Session::Playing {
+            diverged_at: Some(frame),
+            ..
+        } => Err(format!(
+            "Playback diverged from the movie at frame {}.",
+            frame
+        )),
+        Session::Playing { .. } => {
+            println!("Playback matched the movie.");
+            Ok(())
+        }
+    }
+}
+
+/// Runs in a window with sound and keyboard input.
+#[cfg(feature = "sdl")]
+fn run_window(
+    options: &Options,
+    cartridge: &Cartridge,
+    rom_info: Option<&RomInfo>,
+    cpu: &mut Processor,
+    mut movie: Option<&mut Session>,
+) -> Result<(), String> {
+    let config = Config::load(&options.rom_path, rom_info)?;
     let sdl_context = sdl2::init().unwrap();
-    let mut video_out = Video::new(&sdl_context);
-    let mut keyboard = Keyboard::new(&sdl_context);
-    // 1 Mhz clock
+    let palette = rom_info.and_then(|info| info.palette).unwrap_or(PALETTE);
+    let mut video_out = Video::new(&sdl_context, palette);
+    let controllers = Controllers::new(&sdl_context, &config.controller)?;
+    let mut keyboard = Keyboard::new(&sdl_context, &config.keymap, controllers)?;
+    let mut audio_out: Box<dyn Audio> = match &options.audio_output {
+        AudioOutput::Speaker => Box::new(SdlAudio::new(&sdl_context, options.audio)?),
+        AudioOutput::Mute => Box::new(NullAudio),
+        AudioOutput::Wav(path) => {
+            Box::new(WavAudio::create(path, options.audio).map_err(|e| format!("{}: {}", path, e))?)
+        }
+    };
+    let mut cpu_hz = options.cpu_hz;
+    let mut scheduler = Scheduler::new(cpu_hz, options.timing);
+    let mut rewind = Rewind::new(options.rewind_seconds.saturating_mul(FRAME_RATE) as usize);
+    let mut debugger = if options.debug {
+        let mut debugger = Debugger::new();
+        debugger.set_labels(cartridge.labels());
+        Some(debugger)
+    } else {
+        None
+    };
+    let mut keyboard_input = [false; 16];
+    let mut frame = 0;
+    let mut paused = false;
     'running: loop {
-        let key_res = keyboard.handle_input();
-        let keyboard_input = match key_res {
+        match keyboard.handle_input() {
             KeyboardInput::Quit => break 'running,
-            KeyboardInput::Input(input) => input,
+            KeyboardInput::Input(input) => keyboard_input = input,
+            KeyboardInput::Pause => {
+                paused = !paused;
+                println!("{}", if paused { "Paused." } else { "Resumed." });
+            }
+            // Jumping around in time or changing speed would make the movie impossible to replay.
+            KeyboardInput::SaveState(_)
+            | KeyboardInput::LoadState(_)
+            | KeyboardInput::Rewind
+            | KeyboardInput::Reset
+            | KeyboardInput::SpeedUp
+            | KeyboardInput::SpeedDown
+                if movie.is_some() =>
+            {
+                eprintln!(
+                    "Save states, rewind, reset and speed changes are unavailable while a movie is recording or playing."
+                );
+            }
+            KeyboardInput::Reset => {
+                let flags = cpu.rpl_flags();
+                *cpu = boot(options, cartridge);
+                cpu.set_rpl_flags(flags);
+                video_out.update(cpu.framebuffer());
+            }
+            KeyboardInput::SpeedUp | KeyboardInput::SpeedDown
+                if options.timing == Timing::CosmacVip =>
+            {
+                eprintln!("The COSMAC VIP timing runs at the VIP's own speed.");
+            }
+            KeyboardInput::SpeedUp => {
+                cpu_hz = cpu_hz.saturating_mul(2);
+                scheduler = Scheduler::new(cpu_hz, options.timing);
+                println!("Running {} instructions per second.", cpu_hz);
+            }
+            KeyboardInput::SpeedDown => {
+                cpu_hz = (cpu_hz / 2).max(FRAME_RATE);
+                scheduler = Scheduler::new(cpu_hz, options.timing);
+                println!("Running {} instructions per second.", cpu_hz);
+            }
+            KeyboardInput::SaveState(slot) => {
+                let path = state_path(&options.rom_path, slot);
+                match fs::write(&path, cpu.save_state()) {
+                    Ok(()) => println!("Saved state to {}.", path),
+                    Err(err) => eprintln!("Unable to save {}: {}", path, err),
+                }
+            }
+            KeyboardInput::LoadState(slot) => {
+                let path = state_path(&options.rom_path, slot);
+                match fs::read(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|state| cpu.load_state(&state).map_err(|err| err.to_string()))
+                {
+                    Ok(()) => {
+                        println!("Loaded state from {}.", path);
+                        video_out.update(cpu.framebuffer());
+                    }
+                    Err(err) => eprintln!("Unable to load {}: {}", path, err),
+                }
+            }
+            KeyboardInput::Rewind => {
+                if let Some(state) = rewind.pop() {
+                    cpu.load_state(&state)
+                        .expect("Rewind states are always valid.");
+                    video_out.update(cpu.framebuffer());
+                }
+                audio_out.play(cpu.sound(), Scheduler::frame_duration());
+                scheduler.wait_for_next_frame();
+                continue 'running;
+            }
+        }
+        if paused {
+            let silence = Sound {
+                playing: false,
+                ..cpu.sound()
+            };
+            audio_out.play(silence, Scheduler::frame_duration());
+            scheduler.wait_for_next_frame();
+            continue 'running;
+        }
+
+        let keys = match movie.as_deref() {
+            Some(movie) => movie.keys(frame, keyboard_input),
+            None => keyboard_input,
         };
-        // Emu Display Logic
-        let result = cpu.cycle(keyboard_input);
-        video_out.update(result);
-        sleep(Duration::from_nanos(1000));
+        let mut video_changed = false;
+        let mut exited = false;
+        scheduler.start_frame();
+        while scheduler.frame_has_time() {
+            if let Some(debugger) = debugger.as_mut() {
+                if debugger.should_pause(cpu) {
+                    // Nothing runs while at the prompt, so silence the beep and show the latest frame.
+                    let silence = Sound {
+                        playing: false,
+                        ..cpu.sound()
+                    };
+                    audio_out.play(silence, Duration::from_secs(0));
+                    video_out.update(cpu.framebuffer());
+                    let action = debugger.prompt(cpu, &mut io::stdin().lock(), &mut io::stdout());
+                    if action == DebugAction::Quit {
+                        break 'running;
+                    }
+                }
+            }
+            let result = cpu.cycle(keys).map_err(|err| crash_report(err, cpu))?;
+            if let Some(debugger) = debugger.as_mut() {
+                debugger.after_instruction(cpu);
+            }
+            video_changed |= result.video_changed;
+            if result.exited {
+                exited = true;
+                break;
+            }
+            scheduler.spend(&result);
+        }
+        cpu.tick_timers();
+        if let Some(movie) = movie.as_deref_mut() {
+            movie.end_frame(frame, keys, cpu.framebuffer());
+        }
+        frame += 1;
+
+        if video_changed {
+            video_out.update(cpu.framebuffer());
+        }
+        audio_out.play(cpu.sound(), Scheduler::frame_duration());
+        if options.rewind_seconds > 0 {
+            rewind.push(cpu.save_state());
+        }
+        if exited {
+            break 'running;
+        }
+        scheduler.wait_for_next_frame();
     }
+    Ok(())
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(
+    _options: &Options,
+    _cartridge: &Cartridge,
+    _rom_info: Option<&RomInfo>,
+    _cpu: &mut Processor,
+    _movie: Option<&mut Session>,
+) -> Result<(), String> {
+    Err("This build has no window, enable the sdl feature or use --headless.".to_string())
+}
+
+/// Runs without a window until a stop condition, then saves or prints the display.
+fn run_headless(
+    settings: &HeadlessSettings,
+    dump_path: Option<&str>,
+    cpu: &mut Processor,
+    movie: Option<&mut Session>,
+) -> Result<(), String> {
+    let (reason, frames) =
+        headless::run_movie(cpu, settings, movie).map_err(|err| crash_report(err, cpu))?;
+    println!("Stopped after {} frames: {}.", frames, reason);
+    match dump_path {
+        Some(path) => {
+            let format = ImageFormat::from_path(Path::new(path));
+            fs::write(path, image::encode(cpu.framebuffer(), format))
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        None => print!("{}", image::ascii(cpu.framebuffer())),
+    }
+    Ok(())
+}
+
+/// Prints the registers at the point execution failed, leaving the error itself to be reported by main.
+fn crash_report(err: ExecutionError, cpu: &Processor) -> String {
+    eprintln!("{}", cpu.register_dump());
+    err.to_string()
+}
+
+/// Loads a ROM, Octo source or Octo cartridge. Compile errors already say which file they are in.
+fn load_cartridge(path: &str, load_address: u16) -> Result<Cartridge, String> {
+    Cartridge::load(Path::new(path), load_address).map_err(|e| match e {
+        CartridgeError::Program(e) => e.to_string(),
+        e => format!("{}: {}", path, e),
+    })
+}
+
+/// Writes a ROM and symbol map compiled from Octo source, for chip_8 octo <source path> [-o <ROM path>].
+/// The symbol map is written next to the ROM with a .sym extension.
+fn compile_octo(args: &[String]) -> Result<(), String> {
+    let usage = "Usage: chip_8 octo <source path> [-o <ROM path>] [--platform <name>]";
+    let args = ToolOptions::parse(args, usage, true)?;
+    let output = args.output_path();
+    let program = octo::compile_file(Path::new(&args.path), args.platform.load_address)
+        .map_err(|e| e.to_string())?;
+    let symbols = output.with_extension("sym");
+    fs::write(&output, &program.rom).map_err(|e| format!("{}: {}", output.display(), e))?;
+    fs::write(&symbols, program.symbol_map())
+        .map_err(|e| format!("{}: {}", symbols.display(), e))?;
+    println!(
+        "Wrote {} bytes to {} and {} labels to {}.",
+        program.rom.len(),
+        output.display(),
+        program.labels.len(),
+        symbols.display()
+    );
+    Ok(())
+}
 
+/// Writes a ROM assembled from source, for chip_8 asm <source path> [-o <ROM path>].
+/// The ROM is written next to the source with a .ch8 extension unless -o is given.
+fn assemble(args: &[String]) -> Result<(), String> {
+    let usage = "Usage: chip_8 asm <source path> [-o <ROM path>] [--platform <name>]";
+    let args = ToolOptions::parse(args, usage, true)?;
+    let output = args.output_path();
+    let rom = asm::assemble_file(Path::new(&args.path), args.platform.load_address)
+        .map_err(|e| e.to_string())?;
+    fs::write(&output, &rom).map_err(|e| format!("{}: {}", output.display(), e))?;
+    println!("Wrote {} bytes to {}.", rom.len(), output.display());
     Ok(())
 }
+
+/// Prints a listing of a ROM, for chip_8 disasm <ROM path>.
+fn disassemble(args: &[String]) -> Result<(), String> {
+    let usage = "Usage: chip_8 disasm <ROM path> [--platform <name>]";
+    let args = ToolOptions::parse(args, usage, false)?;
+    let cartridge = load_cartridge(&args.path, args.platform.load_address)?;
+    print!("{}", disasm::listing(cartridge.rom(), &args.platform));
+    Ok(())
+}
+
+/// Save slots are kept next to the ROM, e.g. pong.ch8.state1.
+#[cfg(feature = "sdl")]
+fn state_path(rom_path: &str, slot: u8) -> String {
+    format!("{}.state{}", rom_path, slot)
+}
+
+/// Reads the SUPER-CHIP RPL flags saved by a previous run of the same ROM.
+fn load_rpl_flags(path: &str) -> Option<[u8; 16]> {
+    let contents = fs::read(path).ok()?;
+    let mut flags = [0x0; 16];
+    let len = contents.len().min(16);
+    flags[..len].copy_from_slice(&contents[..len]);
+    Some(flags)
+}
@@ -0,0 +1,247 @@
+use crate::error::MovieError;
+use crate::font::Fonts;
+use crate::framebuffer::Framebuffer;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::state::{StateReader, StateWriter};
+use crate::timing::Timing;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+pub const MOVIE_VERSION: u16 = 5;
+
+/// A recording of a run: the settings it started with and the keys held in every frame, with a checksum of the
+/// display at the end of each frame to tell whether a replay turned out the same.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    pub rom_crc: u32, // CRC-32 of the ROM the movie was recorded with.
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub fonts: Fonts,
+    pub platform: Platform,
+    pub timing: Timing,
+    pub cpu_hz: u32,
+    frames: Vec<(u16, u32)>, // Held keys as a bitmask with key n in bit n, and the display's CRC-32.
+}
+
+impl Movie {
+    pub fn new(
+        rom: &[u8],
+        seed: u64,
+        quirks: Quirks,
+        fonts: Fonts,
+        platform: Platform,
+        timing: Timing,
+        cpu_hz: u32,
+    ) -> Movie {
+        Movie {
+            rom_crc: crc32fast::hash(rom),
+            seed,
+            quirks,
+            fonts,
+            platform,
+            timing,
+            cpu_hz,
+            frames: Vec::new(),
+        }
+    }
+
+    /// The number of frames recorded.
+    pub fn len(&self) -> u32 {
+        self.frames.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Adds a frame: the keys held during it and the display at its end.
+    pub fn push(&mut self, keys: [bool; 16], display: &Framebuffer) {
+        let mask = (0..16)
+            .filter(|&key| keys[key])
+            .fold(0, |mask, key| mask | 1 << key);
+        self.frames.push((mask, display_checksum(display)));
+    }
+
+    /// The keys held during a frame, or None past the end of the movie.
+    pub fn keys(&self, frame: u32) -> Option<[bool; 16]> {
+        let &(mask, _) = self.frames.get(frame as usize)?;
+        let mut keys = [false; 16];
+        for (key, held) in keys.iter_mut().enumerate() {
+            *held = mask & 1 << key != 0;
+        }
+        Some(keys)
+    }
+
+    /// Whether the display at the end of a frame is the one recorded. Frames past the end always match.
+    pub fn matches(&self, frame: u32, display: &Framebuffer) -> bool {
+        self.frames
+            .get(frame as usize)
+            .is_none_or(|&(_, checksum)| checksum == display_checksum(display))
+    }
+
+    /// Checks the movie was recorded with this ROM.
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), MovieError> {
+        let loaded = crc32fast::hash(rom);
+        if loaded != self.rom_crc {
+            return Err(MovieError::WrongRom {
+                recorded: self.rom_crc,
+                loaded,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut movie = StateWriter::new();
+        movie.put_u32(self.rom_crc);
+        movie.put_u64(self.seed);
+        self.quirks.write_to(&mut movie);
+        self.fonts.write_to(&mut movie);
+        self.platform.write_to(&mut movie);
+        self.timing.write_to(&mut movie);
+        movie.put_u32(self.cpu_hz);
+        movie.put_u32(self.len());
+        for &(keys, checksum) in self.frames.iter() {
+            movie.put_u16(keys);
+            movie.put_u32(checksum);
+        }
+        movie.finish_as(MAGIC, MOVIE_VERSION)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut movie = StateReader::open(data, MAGIC, MOVIE_VERSION)?;
+        let rom_crc = movie.get_u32()?;
+        let seed = movie.get_u64()?;
+        let quirks = Quirks::read_from(&mut movie)?;
+        let fonts = Fonts::read_from(&mut movie)?;
+        let platform = Platform::read_from(&mut movie)?;
+        let timing = Timing::read_from(&mut movie)?;
+        let cpu_hz = movie.get_u32()?;
+        let len = movie.get_u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..len {
+            frames.push((movie.get_u16()?, movie.get_u32()?));
+        }
+        Ok(Movie {
+            rom_crc,
+            seed,
+            quirks,
+            fonts,
+            platform,
+            timing,
+            cpu_hz,
+            frames,
+        })
+    }
+}
+
+fn display_checksum(display: &Framebuffer) -> u32 {
+    crc32fast::hash(display.pixels())
+}
+
+/// A movie being recorded or played back while the emulator runs.
+pub enum Session {
+    Recording(Movie),
+    Playing {
+        movie: Movie,
+        diverged_at: Option<u32>, // The first frame whose display differed from the recording.
+    },
+}
+
+impl Session {
+    /// The keys to use for a frame: the recorded ones when playing, or else those actually held.
+    pub fn keys(&self, frame: u32, held: [bool; 16]) -> [bool; 16] {
+        match self {
+            Session::Recording(_) => held,
+            Session::Playing { movie, .. } => movie.keys(frame).unwrap_or(held),
+        }
+    }
+
+    /// Records or checks a frame once it has run.
+    pub fn end_frame(&mut self, frame: u32, keys: [bool; 16], display: &Framebuffer) {
+        match self {
+            Session::Recording(movie) => movie.push(keys, display),
+            Session::Playing { movie, diverged_at } => {
+                if diverged_at.is_none() && !movie.matches(frame, display) {
+                    *diverged_at = Some(frame);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::{LORES_HEIGHT, LORES_WIDTH};
+
+    fn recording() -> Movie {
+        let mut movie = Movie::new(
+            &[0x12, 0x00],
+            42,
+            Quirks::CHIP_48,
+            Fonts::default(),
+            Platform::HP_48,
+            Timing::CosmacVip,
+            600,
+        );
+        let mut display = Framebuffer::new(LORES_WIDTH, LORES_HEIGHT);
+        let mut keys = [false; 16];
+        movie.push(keys, &display);
+        keys[0x0] = true;
+        keys[0xF] = true;
+        display.set(5, 5, 1);
+        movie.push(keys, &display);
+        movie
+    }
+
+    #[test]
+    fn movies_survive_encoding() {
+        let movie = recording();
+        assert_eq!(Movie::decode(&movie.encode()), Ok(movie));
+    }
+
+    #[test]
+    fn keys_are_played_back() {
+        let movie = recording();
+        assert_eq!(movie.keys(0), Some([false; 16]));
+        let keys = movie.keys(1).unwrap();
+        assert!(keys[0x0] && keys[0xF]);
+        assert_eq!(keys.iter().filter(|&&held| held).count(), 2);
+        assert_eq!(movie.keys(2), None);
+    }
+
+    #[test]
+    fn playback_reports_the_first_divergence() {
+        let mut session = Session::Playing {
+            movie: recording(),
+            diverged_at: None,
+        };
+        let display = Framebuffer::new(LORES_WIDTH, LORES_HEIGHT);
+        session.end_frame(0, [false; 16], &display);
+        session.end_frame(1, [false; 16], &display);
+        session.end_frame(2, [false; 16], &display);
+        assert!(matches!(
+            session,
+            Session::Playing {
+                diverged_at: Some(1),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn movies_only_play_with_their_rom() {
+        let movie = recording();
+        assert_eq!(movie.check_rom(&[0x12, 0x00]), Ok(()));
+        assert!(movie.check_rom(&[0x12, 0x02]).is_err());
+    }
+
+    #[test]
+    fn save_states_are_not_movies() {
+        assert_eq!(
+            Movie::decode(&StateWriter::new().finish()),
+            Err(MovieError::Unreadable(crate::error::StateError::BadMagic))
+        );
+    }
+}
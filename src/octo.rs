@@ -0,0 +1,1101 @@
+use crate::error::AssemblyError;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+/// Guards against macros that expand to themselves.
+const MAX_MACRO_EXPANSIONS: usize = 10000;
+
+/// A compiled Octo program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub rom: Vec<u8>,                  // Loaded at the origin it was compiled for.
+    pub labels: BTreeMap<String, u16>, // The address of every : label.
+}
+
+impl Program {
+    /// The labels as text, one "address name" pair per line in address order.
+    pub fn symbol_map(&self) -> String {
+        let mut labels: Vec<(&u16, &String)> = self
+            .labels
+            .iter()
+            .map(|(name, addr)| (addr, name))
+            .collect();
+        labels.sort();
+        labels
+            .iter()
+            .map(|(addr, name)| format!("{:04X} {}\n", addr, name))
+            .collect()
+    }
+}
+
+/// Compiles Octo source text into a program to be loaded at origin, usually the platform's load address.
+pub fn compile(source: &str, origin: u16) -> Result<Program, AssemblyError> {
+    Compiler::new("<input>", source, origin).compile()
+}
+
+/// Compiles an Octo source file, usually with the .8o extension.
+pub fn compile_file(path: &Path, origin: u16) -> Result<Program, AssemblyError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|e| AssemblyError {
+        file: name.clone(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    Compiler::new(&name, &source, origin).compile()
+}
+
+/// A whitespace separated word of source and where it starts.
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How a forward reference to a label is filled in once the label is defined.
+#[derive(Clone, Copy, Debug)]
+enum Fixup {
+    Nnn,        // The low 12 bits of the instruction at the address.
+    Long,       // The 16-bit word at the address.
+    Unpack(u8), // v0 := nibble and the high address bits, v1 := the low byte.
+}
+
+/// A control structure waiting for its end.
+enum Block {
+    Loop { start: usize, breaks: Vec<usize> }, // breaks are the jumps emitted by while.
+    Branch { jump: usize },                    // The jump over the then or else part.
+}
+
+struct Compiler {
+    file: String,
+    tokens: VecDeque<Token>,
+    last: Token, // The most recently read token, for errors at the end of the source.
+    rom: Vec<u8>,
+    origin: usize,
+    here: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    fixups: Vec<(usize, Fixup, Token)>,
+    blocks: Vec<(Block, Token)>,
+}
+
+impl Compiler {
+    fn new(file: &str, source: &str, origin: u16) -> Compiler {
+        Compiler {
+            file: file.to_string(),
+            tokens: tokenize(source),
+            last: Token {
+                text: String::new(),
+                line: 1,
+                column: 1,
+            },
+            rom: Vec::new(),
+            origin: origin as usize,
+            here: origin as usize,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn compile(mut self) -> Result<Program, AssemblyError> {
+        // Execution starts at the origin, so jump to main unless it comes first.
+        let starts_with_main = self.tokens.front().map(|t| t.text.as_str()) == Some(":")
+            && self.tokens.get(1).map(|t| t.text.as_str()) == Some("main");
+        if !starts_with_main {
+            let main = Token {
+                text: "main".to_string(),
+                line: 1,
+                column: 1,
+            };
+            self.fixups.push((self.here, Fixup::Nnn, main));
+            self.emit(0x1000)?;
+        }
+
+        while let Some(token) = self.next_token() {
+            self.statement(token)?;
+        }
+
+        if let Some((_, token)) = self.blocks.pop() {
+            return Err(self.error(&token, format!("'{}' is never closed.", token.text)));
+        }
+        for (addr, fixup, token) in std::mem::take(&mut self.fixups) {
+            let value = match self.labels.get(&token.text) {
+                Some(&value) => value,
+                None => {
+                    return Err(self.error(&token, format!("Undefined label '{}'.", token.text)))
+                }
+            };
+            let offset = addr - self.origin;
+            match fixup {
+                Fixup::Nnn => {
+                    if value > 0xFFF {
+                        return Err(self.error(
+                            &token,
+                            format!("'{}' is above 0xFFF, use i := long.", token.text),
+                        ));
+                    }
+                    self.rom[offset] |= (value >> 8) as u8;
+                    self.rom[offset + 1] = value as u8;
+                }
+                Fixup::Long => {
+                    self.rom[offset] = (value >> 8) as u8;
+                    self.rom[offset + 1] = value as u8;
+                }
+                Fixup::Unpack(nibble) => {
+                    self.rom[offset + 1] = nibble << 4 | ((value >> 8) as u8 & 0xF);
+                    self.rom[offset + 3] = value as u8;
+                }
+            }
+        }
+        Ok(Program {
+            rom: self.rom,
+            labels: self.labels,
+        })
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssemblyError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.expect_token()?;
+                self.define_label(name, self.here)?;
+            }
+            // Labels the second byte of the next instruction, usually to modify its operand.
+            ":next" => {
+                let name = self.expect_token()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.expect_token()?;
+                let register = self.expect_token()?;
+                let register = self.register(&register)?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.expect_token()?;
+                let value = self.expect_token()?;
+                let value = self.value(&value)?;
+                self.constants.insert(name.text, value as f64);
+            }
+            ":calc" => {
+                let name = self.expect_token()?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":byte" => {
+                let value = match self.peek() {
+                    Some("{") => self.calc()? as i64,
+                    _ => {
+                        let token = self.expect_token()?;
+                        self.value(&token)?
+                    }
+                };
+                let byte = self.check(&token, value, -0x80, 0xFF)?;
+                self.emit_byte(byte as u8)?;
+            }
+            ":org" => {
+                let addr = self.expect_token()?;
+                let addr = self.value(&addr)?;
+                self.here = self.check(&token, addr, self.origin as i64, 0xFFFF)? as usize;
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let target = self.expect_token()?;
+                self.emit_address(0x2000, &target)?;
+            }
+            ":unpack" => {
+                let nibble = self.expect_token()?;
+                let nibble = self.value(&nibble)?;
+                let nibble = self.check(&token, nibble, 0x0, 0xF)? as u8;
+                let target = self.expect_token()?;
+                self.fixups.push((self.here, Fixup::Unpack(nibble), target));
+                self.emit(0x6000)?;
+                self.emit(0x6100)?;
+            }
+            ":breakpoint" => {
+                self.expect_token()?;
+            }
+            "clear" => self.emit(0x00E0)?,
+            "return" | ";" => self.emit(0x00EE)?,
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "audio" => self.emit(0xF002)?,
+            "scroll-down" | "scroll-up" | "plane" => {
+                let n = self.expect_token()?;
+                let n = self.value(&n)?;
+                let n = self.check(&token, n, 0x0, 0xF)?;
+                self.emit(match token.text.as_str() {
+                    "scroll-down" => 0x00C0 | n,
+                    "scroll-up" => 0x00D0 | n,
+                    _ => 0xF001 | n << 8,
+                })?;
+            }
+            "bcd" | "saveflags" | "loadflags" => {
+                let x = self.expect_register()?;
+                self.emit(
+                    match token.text.as_str() {
+                        "bcd" => 0xF033,
+                        "saveflags" => 0xF075,
+                        _ => 0xF085,
+                    } | x << 8,
+                )?;
+            }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                if self.peek() == Some("-") {
+                    self.expect_token()?;
+                    let y = self.expect_register()?;
+                    let base = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(base | x << 8 | y << 4)?;
+                } else {
+                    let base = if token.text == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(base | x << 8)?;
+                }
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.expect_token()?;
+                let n = self.value(&n)?;
+                let n = self.check(&token, n, 0x0, 0xF)?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "jump" | "jump0" | "native" => {
+                let target = self.expect_token()?;
+                let base = match token.text.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                self.emit_address(base, &target)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                self.emit(
+                    match token.text.as_str() {
+                        "delay" => 0xF015,
+                        "buzzer" => 0xF018,
+                        _ => 0xF03A,
+                    } | x << 8,
+                )?;
+            }
+            "i" => self.index_operation()?,
+            "if" => {
+                self.condition()?;
+                let word = self.expect_token()?;
+                match word.text.as_str() {
+                    "then" => (),
+                    "begin" => {
+                        self.emit(0x1000)?;
+                        let jump = self.here - 2;
+                        self.blocks.push((Block::Branch { jump }, token));
+                    }
+                    _ => return Err(self.error(&word, "Expected 'then' or 'begin'.".to_string())),
+                }
+            }
+            "else" => {
+                let jump = match self.blocks.pop() {
+                    Some((Block::Branch { jump }, _)) => jump,
+                    _ => return Err(self.error(&token, "'else' without 'begin'.".to_string())),
+                };
+                self.emit(0x1000)?;
+                let end = self.here - 2;
+                self.patch_jump(jump, self.here, &token)?;
+                self.blocks.push((Block::Branch { jump: end }, token));
+            }
+            "end" => match self.blocks.pop() {
+                Some((Block::Branch { jump }, _)) => self.patch_jump(jump, self.here, &token)?,
+                _ => return Err(self.error(&token, "'end' without 'begin'.".to_string())),
+            },
+            "loop" => {
+                let block = Block::Loop {
+                    start: self.here,
+                    breaks: Vec::new(),
+                };
+                self.blocks.push((block, token));
+            }
+            "while" => {
+                if !self
+                    .blocks
+                    .iter()
+                    .any(|(block, _)| matches!(block, Block::Loop { .. }))
+                {
+                    return Err(self.error(&token, "'while' outside a loop.".to_string()));
+                }
+                self.condition()?;
+                self.emit(0x1000)?;
+                let jump = self.here - 2;
+                let innermost = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { breaks, .. } => Some(breaks),
+                        Block::Branch { .. } => None,
+                    });
+                innermost.expect("A loop was found above.").push(jump);
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, breaks }, _)) => {
+                    self.emit(0x1000)?;
+                    self.patch_jump(self.here - 2, start, &token)?;
+                    for jump in breaks {
+                        self.patch_jump(jump, self.here, &token)?;
+                    }
+                }
+                _ => return Err(self.error(&token, "'again' without 'loop'.".to_string())),
+            },
+            "then" | "begin" => {
+                return Err(self.error(&token, format!("'{}' without 'if'.", token.text)));
+            }
+            _ => {
+                if let Ok(x) = self.register(&token) {
+                    return self.register_operation(x);
+                }
+                if self.macros.contains_key(&token.text) {
+                    return self.expand_macro(&token);
+                }
+                if let Some(value) = self.number(&token.text) {
+                    let byte = self.check(&token, value, -0x80, 0xFF)?;
+                    return self.emit_byte(byte as u8);
+                }
+                if let Some(&value) = self.constants.get(&token.text) {
+                    let byte = self.check(&token, value as i64, -0x80, 0xFF)?;
+                    return self.emit_byte(byte as u8);
+                }
+                if token.text.starts_with(':') {
+                    return Err(
+                        self.error(&token, format!("Unsupported directive '{}'.", token.text))
+                    );
+                }
+                // Any other name calls the subroutine with that label.
+                self.emit_address(0x2000, &token)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: Token, addr: usize) -> Result<(), AssemblyError> {
+        if self.labels.contains_key(&name.text) {
+            return Err(self.error(&name, format!("'{}' is already defined.", name.text)));
+        }
+        self.labels.insert(name.text, addr as u16);
+        Ok(())
+    }
+
+    /// vx := ..., vx += ... and the other register operations.
+    fn register_operation(&mut self, x: u16) -> Result<(), AssemblyError> {
+        let operator = self.expect_token()?;
+        let operand = self.expect_token()?;
+        let y = self.register(&operand).ok();
+        let opcode = match (operator.text.as_str(), y) {
+            (":=", Some(y)) => 0x8000 | x << 8 | y << 4,
+            ("|=", Some(y)) => 0x8001 | x << 8 | y << 4,
+            ("&=", Some(y)) => 0x8002 | x << 8 | y << 4,
+            ("^=", Some(y)) => 0x8003 | x << 8 | y << 4,
+            ("+=", Some(y)) => 0x8004 | x << 8 | y << 4,
+            ("-=", Some(y)) => 0x8005 | x << 8 | y << 4,
+            (">>=", Some(y)) => 0x8006 | x << 8 | y << 4,
+            ("=-", Some(y)) => 0x8007 | x << 8 | y << 4,
+            ("<<=", Some(y)) => 0x800E | x << 8 | y << 4,
+            (":=", None) => match operand.text.as_str() {
+                "delay" => 0xF007 | x << 8,
+                "key" => 0xF00A | x << 8,
+                "random" => {
+                    let mask = self.expect_token()?;
+                    0xC000 | x << 8 | self.byte(&mask)?
+                }
+                _ => 0x6000 | x << 8 | self.byte(&operand)?,
+            },
+            ("+=", None) => 0x7000 | x << 8 | self.byte(&operand)?,
+            ("-=", None) => {
+                let value = self.value(&operand)?;
+                self.check(&operand, value, -0xFF, 0xFF)?;
+                0x7000 | x << 8 | (value.wrapping_neg() & 0xFF) as u16
+            }
+            _ => {
+                return Err(self.error(&operator, format!("Invalid operator '{}'.", operator.text)))
+            }
+        };
+        self.emit(opcode)
+    }
+
+    /// i := addr, i := long addr, i := hex vx, i := bighex vx and i += vx.
+    fn index_operation(&mut self) -> Result<(), AssemblyError> {
+        let operator = self.expect_token()?;
+        match operator.text.as_str() {
+            "+=" => {
+                let x = self.expect_register()?;
+                self.emit(0xF01E | x << 8)
+            }
+            ":=" => {
+                let operand = self.expect_token()?;
+                match operand.text.as_str() {
+                    "hex" => {
+                        let x = self.expect_register()?;
+                        self.emit(0xF029 | x << 8)
+                    }
+                    "bighex" => {
+                        let x = self.expect_register()?;
+                        self.emit(0xF030 | x << 8)
+                    }
+                    "long" => {
+                        let target = self.expect_token()?;
+                        self.emit(0xF000)?;
+                        match self.known(&target) {
+                            Some(value) => {
+                                let value = self.check(&target, value, 0x0, 0xFFFF)?;
+                                self.emit(value)
+                            }
+                            None => {
+                                self.fixups.push((self.here, Fixup::Long, target));
+                                self.emit(0x0000)
+                            }
+                        }
+                    }
+                    _ => self.emit_address(0xA000, &operand),
+                }
+            }
+            _ => Err(self.error(&operator, format!("Invalid operator '{}'.", operator.text))),
+        }
+    }
+
+    /// Parses vx == n, vx != vy, vx key and the like. Before then, the instructions emitted skip the next one when
+    /// the condition is false. Otherwise they are followed by a jump out of a block, skipped when it is true.
+    /// Comparisons with < and > work out the answer in VF.
+    fn condition(&mut self) -> Result<(), AssemblyError> {
+        let x = self.expect_register()?;
+        let operator = self.expect_token()?;
+        enum Test {
+            Skip(u16),       // An instruction that skips when the condition holds.
+            Flag(u16, bool), // Compute VF with the given subtraction, then the condition holds when VF != 0 is this.
+        }
+        let test = match operator.text.as_str() {
+            "key" => Test::Skip(0xE09E | x << 8),
+            "-key" => Test::Skip(0xE0A1 | x << 8),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let operand = self.expect_token()?;
+                let load = match self.register(&operand) {
+                    Ok(y) => (None, Some(y)),
+                    Err(_) => (Some(self.byte(&operand)?), None),
+                };
+                match (operator.text.as_str(), load) {
+                    ("==", (Some(kk), _)) => Test::Skip(0x3000 | x << 8 | kk),
+                    ("!=", (Some(kk), _)) => Test::Skip(0x4000 | x << 8 | kk),
+                    ("==", (_, Some(y))) => Test::Skip(0x5000 | x << 8 | y << 4),
+                    ("!=", (_, Some(y))) => Test::Skip(0x9000 | x << 8 | y << 4),
+                    (comparison, (kk, y)) => {
+                        // VF := the operand, then VF -= vx sets VF when operand >= vx, and VF =- vx when vx >= operand.
+                        match (kk, y) {
+                            (Some(kk), _) => self.emit(0x6F00 | kk)?,
+                            (_, Some(y)) => self.emit(0x8F00 | y << 4)?,
+                            _ => unreachable!("The operand is a byte or a register."),
+                        }
+                        match comparison {
+                            "<=" => Test::Flag(0x8F05 | x << 4, true),
+                            ">" => Test::Flag(0x8F05 | x << 4, false),
+                            ">=" => Test::Flag(0x8F07 | x << 4, true),
+                            _ => Test::Flag(0x8F07 | x << 4, false),
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(self.error(&operator, format!("Invalid condition '{}'.", operator.text)))
+            }
+        };
+
+        let skip_when_true = self.peek() != Some("then");
+        let opcode = match test {
+            Test::Skip(opcode) if skip_when_true => opcode,
+            Test::Skip(opcode) => invert_skip(opcode),
+            Test::Flag(subtraction, holds_when_set) => {
+                self.emit(subtraction)?;
+                // 4F00 skips when VF is set, 3F00 when it is clear.
+                if holds_when_set == skip_when_true {
+                    0x4F00
+                } else {
+                    0x3F00
+                }
+            }
+        };
+        self.emit(opcode)
+    }
+
+    fn define_macro(&mut self) -> Result<(), AssemblyError> {
+        let name = self.expect_token()?;
+        let mut args = Vec::new();
+        loop {
+            let token = self.expect_token()?;
+            if token.text == "{" {
+                break;
+            }
+            args.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.expect_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    /// Replaces a macro's arguments in its body and queues the result to be compiled next.
+    fn expand_macro(&mut self, name: &Token) -> Result<(), AssemblyError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(self.error(name, format!("Too many expansions of '{}'.", name.text)));
+        }
+        let count = self.macros[&name.text].args.len();
+        let mut values = HashMap::new();
+        for index in 0..count {
+            let value = self.expect_token()?;
+            values.insert(self.macros[&name.text].args[index].clone(), value.text);
+        }
+        let calls = self.expansions.to_string();
+        let body: Vec<Token> = self.macros[&name.text]
+            .body
+            .iter()
+            .map(|token| {
+                let text = match token.text.as_str() {
+                    "CALLS" => calls.clone(),
+                    text => values
+                        .get(text)
+                        .cloned()
+                        .unwrap_or_else(|| text.to_string()),
+                };
+                Token {
+                    text,
+                    ..token.clone()
+                }
+            })
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// Evaluates a { ... } :calc expression. Operators have no precedence and are applied right to left, as in Octo.
+    fn calc(&mut self) -> Result<f64, AssemblyError> {
+        self.expect("{")?;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.expect_token()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let mut position = 0;
+        let value = self.calc_expression(&tokens, &mut position)?;
+        match tokens.get(position) {
+            Some(token) => Err(self.error(token, format!("Unexpected '{}'.", token.text))),
+            None => Ok(value),
+        }
+    }
+
+    fn calc_expression(
+        &self,
+        tokens: &[Token],
+        position: &mut usize,
+    ) -> Result<f64, AssemblyError> {
+        let left = self.calc_term(tokens, position)?;
+        let operator = match tokens.get(*position) {
+            None => return Ok(left),
+            Some(token) if token.text == ")" => return Ok(left),
+            Some(token) => token,
+        };
+        *position += 1;
+        let right = self.calc_expression(tokens, position)?;
+        Ok(match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (left as i64 & right as i64) as f64,
+            "|" => (left as i64 | right as i64) as f64,
+            "^" => (left as i64 ^ right as i64) as f64,
+            "<<" => ((left as i64) << right as i64) as f64,
+            ">>" => (left as i64 >> right as i64) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            _ => return Err(self.error(operator, format!("Unknown operator '{}'.", operator.text))),
+        })
+    }
+
+    fn calc_term(&self, tokens: &[Token], position: &mut usize) -> Result<f64, AssemblyError> {
+        let token = match tokens.get(*position) {
+            Some(token) => token,
+            None => return Err(self.error(&self.last, "Incomplete expression.".to_string())),
+        };
+        *position += 1;
+        let unary = |f: fn(f64) -> f64, position: &mut usize| -> Result<f64, AssemblyError> {
+            Ok(f(self.calc_term(tokens, position)?))
+        };
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression(tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => {
+                        *position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(self.error(token, "Unclosed '('.".to_string())),
+                }
+            }
+            "-" => unary(|x| -x, position),
+            "~" => unary(|x| !(x as i64) as f64, position),
+            "!" => unary(|x| (x == 0.0) as i64 as f64, position),
+            "abs" => unary(f64::abs, position),
+            "sqrt" => unary(f64::sqrt, position),
+            "sin" => unary(f64::sin, position),
+            "cos" => unary(f64::cos, position),
+            "tan" => unary(f64::tan, position),
+            "exp" => unary(f64::exp, position),
+            "log" => unary(f64::ln, position),
+            "sign" => unary(f64::signum, position),
+            "ceil" => unary(f64::ceil, position),
+            "floor" => unary(f64::floor, position),
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text => match self.number(text) {
+                Some(value) => Ok(value as f64),
+                None => match self.constants.get(text) {
+                    Some(&value) => Ok(value),
+                    None => match self.labels.get(text) {
+                        Some(&addr) => Ok(addr as f64),
+                        None => Err(self.error(token, format!("Undefined name '{}'.", text))),
+                    },
+                },
+            },
+        }
+    }
+
+    /// A number, constant or label that is already defined.
+    fn known(&self, token: &Token) -> Option<i64> {
+        self.number(&token.text)
+            .or_else(|| self.constants.get(&token.text).map(|&value| value as i64))
+            .or_else(|| self.labels.get(&token.text).map(|&addr| addr as i64))
+    }
+
+    fn value(&self, token: &Token) -> Result<i64, AssemblyError> {
+        self.known(token)
+            .ok_or_else(|| self.error(token, format!("Undefined name '{}'.", token.text)))
+    }
+
+    fn byte(&self, token: &Token) -> Result<u16, AssemblyError> {
+        let value = self.value(token)?;
+        Ok(self.check(token, value, -0x80, 0xFF)? & 0xFF)
+    }
+
+    /// Decimal, 0x hex or 0b binary, optionally negative.
+    fn number(&self, text: &str) -> Option<i64> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let value = if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(binary) = digits
+            .strip_prefix("0b")
+            .or_else(|| digits.strip_prefix("0B"))
+        {
+            i64::from_str_radix(binary, 2).ok()?
+        } else {
+            digits.parse().ok()?
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn check(&self, token: &Token, value: i64, min: i64, max: i64) -> Result<u16, AssemblyError> {
+        if value < min || value > max {
+            return Err(self.error(token, format!("{} is out of range.", value)));
+        }
+        Ok(value as u16)
+    }
+
+    /// v0 to vF, or an :alias for one.
+    fn register(&self, token: &Token) -> Result<u16, AssemblyError> {
+        if let Some(&register) = self.aliases.get(&token.text) {
+            return Ok(register);
+        }
+        let digit = token
+            .text
+            .strip_prefix('v')
+            .or_else(|| token.text.strip_prefix('V'));
+        match digit {
+            Some(digit) if digit.len() == 1 => u16::from_str_radix(digit, 16)
+                .map_err(|_| self.error(token, format!("'{}' is not a register.", token.text))),
+            _ => Err(self.error(token, format!("'{}' is not a register.", token.text))),
+        }
+    }
+
+    fn expect_register(&mut self) -> Result<u16, AssemblyError> {
+        let token = self.expect_token()?;
+        self.register(&token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssemblyError> {
+        let token = self.expect_token()?;
+        if token.text != text {
+            return Err(self.error(&token, format!("Expected '{}'.", text)));
+        }
+        Ok(())
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.last = token.clone();
+        Some(token)
+    }
+
+    fn expect_token(&mut self) -> Result<Token, AssemblyError> {
+        match self.next_token() {
+            Some(token) => Ok(token),
+            None => Err(self.error(&self.last, "Unexpected end of file.".to_string())),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    /// Emits an instruction with a 12-bit address, filled in later if the label is not defined yet.
+    fn emit_address(&mut self, base: u16, target: &Token) -> Result<(), AssemblyError> {
+        match self.known(target) {
+            Some(value) => {
+                let addr = self.check(target, value, 0x0, 0xFFF)?;
+                self.emit(base | addr)
+            }
+            None => {
+                self.fixups.push((self.here, Fixup::Nnn, target.clone()));
+                self.emit(base)
+            }
+        }
+    }
+
+    /// Fills in the address of a jump emitted by a control structure. token is the word that closes it, which
+    /// errors are reported at.
+    fn patch_jump(
+        &mut self,
+        jump: usize,
+        target: usize,
+        token: &Token,
+    ) -> Result<(), AssemblyError> {
+        if target > 0xFFF {
+            return Err(self.error(
+                token,
+                format!(
+                    "'{}' needs a jump to {:#X}, above the 0xFFF jumps can reach.",
+                    token.text, target
+                ),
+            ));
+        }
+        let offset = jump - self.origin;
+        self.rom[offset] = 0x10 | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<(), AssemblyError> {
+        self.emit_byte((opcode >> 8) as u8)?;
+        self.emit_byte(opcode as u8)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AssemblyError> {
+        if self.here > 0xFFFF {
+            return Err(self.error(
+                &self.last,
+                "The program does not fit in memory.".to_string(),
+            ));
+        }
+        let offset = self.here - self.origin;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0x0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn error(&self, token: &Token, message: String) -> AssemblyError {
+        AssemblyError {
+            file: self.file.clone(),
+            line: token.line,
+            column: token.column,
+            message,
+        }
+    }
+}
+
+/// Swaps a skip instruction for the one that skips in the opposite case.
+fn invert_skip(opcode: u16) -> u16 {
+    match opcode & 0xF00F {
+        0x5000 => opcode ^ 0xC000, // 5xy0 and 9xy0.
+        0x9000 => opcode ^ 0xC000,
+        _ => match opcode & 0xF0FF {
+            0xE09E => opcode & 0xFF00 | 0xA1,
+            0xE0A1 => opcode & 0xFF00 | 0x9E,
+            _ => opcode ^ 0x7000, // 3xkk and 4xkk.
+        },
+    }
+}
+
+/// Splits source into whitespace separated tokens, dropping # comments.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut start = None;
+        for (column, c) in line.chars().chain(std::iter::once(' ')).enumerate() {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(column),
+                (true, Some(first)) => {
+                    tokens.push_back(Token {
+                        text: line.chars().skip(first).take(column - first).collect(),
+                        line: index + 1,
+                        column: first + 1,
+                    });
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        compile(source, 0x200)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .rom
+    }
+
+    fn error(source: &str) -> String {
+        compile(source, 0x200).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn programs_jump_to_main_unless_it_comes_first() {
+        assert_eq!(rom(": main clear"), [0x00, 0xE0]);
+        assert_eq!(
+            rom(": sub return : main sub"),
+            [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]
+        );
+    }
+
+    #[test]
+    fn if_then_skips_one_instruction() {
+        assert_eq!(
+            rom(": main if v0 == 1 then v1 := 2"),
+            [0x40, 0x01, 0x61, 0x02]
+        );
+        assert_eq!(
+            rom(": main if v0 != v2 then v1 := 2"),
+            [0x50, 0x20, 0x61, 0x02]
+        );
+        assert_eq!(rom(": main if v3 key then clear"), [0xE3, 0xA1, 0x00, 0xE0]);
+    }
+
+    #[test]
+    fn if_begin_else_end_jumps_around_each_part() {
+        assert_eq!(
+            rom(": main if v0 == 1 begin v1 := 2 else v1 := 3 end"),
+            [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0A, 0x61, 0x03]
+        );
+        assert_eq!(
+            rom(": main if v0 == 1 begin clear end"),
+            [0x30, 0x01, 0x12, 0x06, 0x00, 0xE0]
+        );
+    }
+
+    #[test]
+    fn comparisons_are_worked_out_in_vf() {
+        // VF := 5, VF -= v0, then skip the jump out when VF is set, i.e. v0 <= 5.
+        assert_eq!(
+            rom(": main if v0 <= 5 begin clear end"),
+            [0x6F, 0x05, 0x8F, 0x05, 0x4F, 0x00, 0x12, 0x0A, 0x00, 0xE0]
+        );
+    }
+
+    #[test]
+    fn loops_repeat_until_a_while_fails() {
+        assert_eq!(
+            rom(": main loop v0 += 1 while v0 != 5 again"),
+            [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00]
+        );
+        // Each while leaves the innermost loop.
+        assert_eq!(
+            rom(": main loop loop while v0 == 1 again while v1 == 1 again"),
+            [0x30, 0x01, 0x12, 0x06, 0x12, 0x00, 0x31, 0x01, 0x12, 0x0C, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn macros_substitute_their_arguments() {
+        assert_eq!(
+            rom(": main :macro twice X { X X } twice clear"),
+            [0x00, 0xE0, 0x00, 0xE0]
+        );
+        assert_eq!(
+            rom(": main :macro set R V { R := V } set v3 7 set v4 0x10"),
+            [0x63, 0x07, 0x64, 0x10]
+        );
+        assert_eq!(
+            rom(": main :macro count { :byte CALLS } count count"),
+            [0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn calc_applies_operators_right_to_left() {
+        assert_eq!(rom(": main :calc X { 2 * 3 + 1 } v0 := X"), [0x60, 0x08]);
+        assert_eq!(
+            rom(": main :calc X { ( 2 * 3 ) + 1 } v0 := X"),
+            [0x60, 0x07]
+        );
+        assert_eq!(rom(": main :calc X { 10 - 2 - 3 } v0 := X"), [0x60, 0x0B]);
+        assert_eq!(
+            rom(": main :const A 4 :calc B { A << 2 | 1 } v0 := B"),
+            [0x60, 0x20]
+        );
+        assert_eq!(
+            rom(": main :calc H { HERE + 2 } :byte { H - 0x200 }"),
+            [0x02]
+        );
+    }
+
+    #[test]
+    fn unpack_loads_a_label_into_v0_and_v1() {
+        assert_eq!(
+            rom(": main :unpack 0xA data : data"),
+            [0x60, 0xA2, 0x61, 0x04]
+        );
+        assert_eq!(rom(": main :unpack 1 main"), [0x60, 0x12, 0x61, 0x00]);
+    }
+
+    #[test]
+    fn next_labels_the_operand_of_the_next_instruction() {
+        let program = compile(": main :next target v0 := 5 i := target", 0x200).unwrap();
+        assert_eq!(program.labels["target"], 0x201);
+        assert_eq!(program.rom, [0x60, 0x05, 0xA2, 0x01]);
+    }
+
+    #[test]
+    fn long_loads_reach_all_of_memory() {
+        let program = compile(": main i := long data :org 0x8000 : data", 0x200).unwrap();
+        assert_eq!(program.rom, [0xF0, 0x00, 0x80, 0x00]);
+        assert_eq!(
+            error(": main i := data :org 0x1000 : data"),
+            "<input>:1:13: 'data' is above 0xFFF, use i := long."
+        );
+    }
+
+    #[test]
+    fn control_structures_cannot_jump_above_0xfff() {
+        assert_eq!(
+            error(": main jump main :org 0x1000 : far loop again"),
+            "<input>:1:41: 'again' needs a jump to 0x1000, above the 0xFFF jumps can reach."
+        );
+        assert_eq!(
+            error(": main if v0 == 1 begin :org 0x1000 clear end"),
+            "<input>:1:43: 'end' needs a jump to 0x1002, above the 0xFFF jumps can reach."
+        );
+        assert_eq!(
+            error(": main loop while v0 == 1 :org 0x1000 again"),
+            "<input>:1:39: 'again' needs a jump to 0x1002, above the 0xFFF jumps can reach."
+        );
+    }
+
+    #[test]
+    fn errors_say_where_they_are() {
+        assert_eq!(
+            error(": main\n  jump nowhere"),
+            "<input>:2:8: Undefined label 'nowhere'."
+        );
+        assert_eq!(
+            error(": main\n  loop"),
+            "<input>:2:3: 'loop' is never closed."
+        );
+        assert_eq!(error(": main else"), "<input>:1:8: 'else' without 'begin'.");
+        assert_eq!(
+            error(": main again"),
+            "<input>:1:8: 'again' without 'loop'."
+        );
+        assert_eq!(
+            error(": main while v0 == 1"),
+            "<input>:1:8: 'while' outside a loop."
+        );
+        assert_eq!(error(": main then"), "<input>:1:8: 'then' without 'if'.");
+        assert_eq!(
+            error(": main : main"),
+            "<input>:1:10: 'main' is already defined."
+        );
+        assert_eq!(
+            error(": main v0 := 256"),
+            "<input>:1:14: 256 is out of range."
+        );
+        assert_eq!(
+            error(": main v0 ~= v1"),
+            "<input>:1:11: Invalid operator '~='."
+        );
+        assert_eq!(
+            error(": main :frob"),
+            "<input>:1:8: Unsupported directive ':frob'."
+        );
+        assert_eq!(
+            error(": main v0 :="),
+            "<input>:1:11: Unexpected end of file."
+        );
+        assert_eq!(
+            error(":macro forever { forever } : main forever"),
+            "<input>:1:18: Too many expansions of 'forever'."
+        );
+    }
+}
@@ -0,0 +1,415 @@
+#[cfg(feature = "sdl")]
+use chip_8::audio::{AudioSettings, Waveform};
+use chip_8::database::RomInfo;
+use chip_8::font::{BigFont, Fonts, SmallFont};
+use chip_8::headless::{HeadlessSettings, KeyScript};
+use chip_8::platform::Platform;
+use chip_8::quirks::Quirks;
+use chip_8::scheduler::{DEFAULT_CPU_HZ, FRAME_RATE};
+use chip_8::timing::Timing;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const USAGE: &str = "Usage: chip_8 [options] <ROM or .8o Octo source path>
+       chip_8 asm <source path> [-o <ROM path>] [--platform <name>]
+       chip_8 disasm <ROM path> [--platform <name>]
+       chip_8 octo <source path> [-o <ROM path>] [--platform <name>]
+Options:
+    --quirks <vip|chip48|schip|xochip|modern>
+    --platform <vip|hires|eti660|hp48|xochip> Memory layout of the machine. By default vip for .ch8 ROMs, hp48 for
+                                              .sc8 and xochip for the rest.
+    --memory <4|64>                           KB of memory, overriding the platform's.
+    --stack-depth <n>                         How deeply subroutines can nest, overriding the platform's.
+    --cpu-hz <n>                              Instructions per second, 700 by default.
+    --ipf <n>                                 Instructions per 60 Hz frame.
+    --timing <uniform|vip>                    vip takes as long over each instruction as the COSMAC VIP,
+                                              and draws at most one sprite a frame. uniform by default.
+    --font <chip48|vip|dream6800|eti660|fishnchips>
+                                              The 4x5 digits Fx29 draws, chip48 by default.
+    --big-font <octo|schip>                   The 8x10 digits Fx30 draws, octo by default.
+    --font-address <hex address>              Where the fonts go in memory, 50 by default.
+    --no-database                             Ignore the settings the ROM database has for known ROMs.
+    --seed <n>                                Seed for random numbers, to repeat a run exactly. Random by default.
+    --debug                                   Start paused at a debugger prompt on the terminal.
+    --rewind <seconds>                        How far back holding backspace can go, 10 by default.
+    --frequency <Hz>                          Pitch of the beep.
+    --volume <0-1>
+    --waveform <square|triangle|sawtooth|sine>
+    --mute                                    Play no sound.
+    --wav <path>                              Write the sound to a WAV file instead of playing it.
+    --record <path>                           Record the keys pressed in every frame to a movie file.
+    --play <path>                             Play back a movie, reporting the first frame that turns out differently.
+    --headless                                Run without a window, as fast as possible. Needs a stop condition:
+    --frames <n>                                Stop after n frames.
+    --until-pc <hex address>                    Stop before executing the instruction at an address.
+    --until-loop                                Stop at an instruction that jumps to itself.
+    --keys <script|@path>                     Keys to hold when headless, e.g. \"60:5 62: 120:46\" holds 5 for
+                                              frames 60 and 61 then 4 and 6 from frame 120.
+    --dump <path>                             Save the final display as .png, .pbm or ASCII art, or print it.";
+
+/// Where sound is sent.
+#[cfg(feature = "sdl")]
+pub enum AudioOutput {
+    Speaker,
+    Mute,
+    Wav(String),
+}
+
+/// Settings given on the command line.
+pub struct Options {
+    pub rom_path: String,
+    pub quirks: Quirks,
+    pub platform: Platform,
+    pub fonts: Fonts,
+    pub cpu_hz: u32,
+    pub timing: Timing,
+    pub seed: Option<u64>,
+    #[cfg(feature = "sdl")]
+    pub rewind_seconds: u32,
+    #[cfg(feature = "sdl")]
+    pub debug: bool,
+    #[cfg(feature = "sdl")]
+    pub audio: AudioSettings,
+    #[cfg(feature = "sdl")]
+    pub audio_output: AudioOutput,
+    pub record_path: Option<String>,
+    pub play_path: Option<String>,
+    pub headless: Option<HeadlessSettings>,
+    pub dump_path: Option<String>,
+    pub use_database: bool,
+    memory_size: Option<usize>, // Given on the command line, these override the platform's.
+    stack_depth: Option<usize>,
+    quirks_given: bool, // Whether the quirks, speed and platform were chosen on the command line, so the
+    speed_given: bool,  // database leaves them alone.
+    platform_given: bool,
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut rom_path = None;
+        let mut quirks = Quirks::default();
+        let mut platform = Platform::default();
+        let mut fonts = Fonts::default();
+        let mut cpu_hz = DEFAULT_CPU_HZ;
+        let mut timing = Timing::Uniform;
+        let mut seed = None;
+        #[cfg(feature = "sdl")]
+        let mut rewind_seconds = 10;
+        #[cfg(feature = "sdl")]
+        let mut debug = false;
+        #[cfg(feature = "sdl")]
+        let mut audio = AudioSettings::default();
+        #[cfg(feature = "sdl")]
+        let mut audio_output = AudioOutput::Speaker;
+        let mut record_path = None;
+        let mut play_path = None;
+        let mut headless = false;
+        let mut max_frames = None;
+        let mut stop_at = None;
+        let mut stop_on_loop = false;
+        let mut keys = KeyScript::default();
+        let mut dump_path = None;
+        let mut use_database = true;
+        let mut quirks_given = false;
+        let mut speed_given = false;
+        let mut platform_given = false;
+        let mut memory_size = None;
+        let mut stack_depth = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = args.next().ok_or("--quirks requires a profile name.")?;
+                    quirks = Quirks::from_name(name)
+                        .ok_or_else(|| format!("Unknown quirk profile '{}'.", name))?;
+                    quirks_given = true;
+                }
+                "--platform" => {
+                    let name = args.next().ok_or("--platform requires a name.")?;
+                    platform = Platform::from_name(name)
+                        .ok_or_else(|| format!("Unknown platform '{}'.", name))?;
+                    platform_given = true;
+                }
+                "--memory" => match args.next().map(String::as_str) {
+                    Some("4") => memory_size = Some(0x1000),
+                    Some("64") => memory_size = Some(0x10000),
+                    _ => return Err("--memory must be 4 or 64 (KB).".to_string()),
+                },
+                "--stack-depth" => {
+                    let depth = parse_number(arg, args.next())?;
+                    if !(1..=255).contains(&depth) {
+                        return Err("--stack-depth must be between 1 and 255.".to_string());
+                    }
+                    stack_depth = Some(depth);
+                }
+                "--cpu-hz" => {
+                    cpu_hz = parse_nonzero(arg, args.next())?;
+                    speed_given = true;
+                }
+                "--ipf" => {
+                    cpu_hz = parse_nonzero(arg, args.next())?
+                        .checked_mul(FRAME_RATE)
+                        .ok_or("--ipf is too large.")?;
+                    speed_given = true;
+                }
+                "--timing" => {
+                    let name = args.next().ok_or("--timing requires a name.")?;
+                    timing = Timing::from_name(name)
+                        .ok_or_else(|| format!("Unknown timing '{}'.", name))?;
+                }
+                "--font" => {
+                    let name = args.next().ok_or("--font requires a name.")?;
+                    fonts.small = SmallFont::from_name(name)
+                        .ok_or_else(|| format!("Unknown font '{}'.", name))?;
+                }
+                "--big-font" => {
+                    let name = args.next().ok_or("--big-font requires a name.")?;
+                    fonts.big = BigFont::from_name(name)
+                        .ok_or_else(|| format!("Unknown big font '{}'.", name))?;
+                }
+                "--font-address" => fonts.address = parse_address(arg, args.next())?,
+                "--no-database" => use_database = false,
+                "--seed" => seed = Some(parse_number(arg, args.next())?),
+                #[cfg(feature = "sdl")]
+                "--debug" => debug = true,
+                #[cfg(feature = "sdl")]
+                "--rewind" => rewind_seconds = parse_number(arg, args.next())?,
+                #[cfg(feature = "sdl")]
+                "--frequency" => audio.frequency = parse_number(arg, args.next())?,
+                #[cfg(feature = "sdl")]
+                "--volume" => {
+                    audio.volume = parse_number(arg, args.next())?;
+                    if !(0.0..=1.0).contains(&audio.volume) {
+                        return Err("--volume must be between 0 and 1.".to_string());
+                    }
+                }
+                #[cfg(feature = "sdl")]
+                "--waveform" => {
+                    let name = args.next().ok_or("--waveform requires a name.")?;
+                    audio.waveform = Waveform::from_name(name)
+                        .ok_or_else(|| format!("Unknown waveform '{}'.", name))?;
+                }
+                #[cfg(feature = "sdl")]
+                "--mute" => audio_output = AudioOutput::Mute,
+                #[cfg(feature = "sdl")]
+                "--wav" => {
+                    let path = args.next().ok_or("--wav requires a file path.")?;
+                    audio_output = AudioOutput::Wav(path.clone());
+                }
+                "--record" => {
+                    let path = args.next().ok_or("--record requires a file path.")?;
+                    record_path = Some(path.clone());
+                }
+                "--play" => {
+                    let path = args.next().ok_or("--play requires a file path.")?;
+                    play_path = Some(path.clone());
+                }
+                "--headless" => headless = true,
+                "--frames" => max_frames = Some(parse_nonzero(arg, args.next())?),
+                "--until-pc" => stop_at = Some(parse_address(arg, args.next())?),
+                "--until-loop" => stop_on_loop = true,
+                "--keys" => {
+                    let script = args.next().ok_or("--keys requires a script.")?;
+                    let script = match script.strip_prefix('@') {
+                        Some(path) => {
+                            fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?
+                        }
+                        None => script.clone(),
+                    };
+                    keys = KeyScript::parse(&script)?;
+                }
+                "--dump" => {
+                    let path = args.next().ok_or("--dump requires a file path.")?;
+                    dump_path = Some(path.clone());
+                }
+                #[cfg(not(feature = "sdl"))]
+                "--debug" | "--rewind" | "--frequency" | "--volume" | "--waveform" | "--mute"
+                | "--wav" => {
+                    return Err(format!(
+                        "{} needs a window, and this build has no sdl feature.",
+                        arg
+                    ))
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'.", arg)),
+                _ => rom_path = Some(arg.clone()),
+            }
+        }
+
+        let rom_path = rom_path
+            .ok_or_else(|| format!("A path to a valid *.ch8 file must be provided.\n{}", USAGE))?;
+
+        if record_path.is_some() && play_path.is_some() {
+            return Err("--record and --play cannot be used together.".to_string());
+        }
+        let headless = if headless {
+            // A movie being played back stops at its end.
+            if max_frames.is_none() && stop_at.is_none() && !stop_on_loop && play_path.is_none() {
+                return Err(
+                    "--headless requires --frames, --until-pc, --until-loop or --play.".to_string(),
+                );
+            }
+            Some(HeadlessSettings {
+                cpu_hz,
+                timing,
+                max_frames,
+                stop_at,
+                stop_on_loop,
+                keys,
+            })
+        } else {
+            None
+        };
+
+        let mut options = Options {
+            rom_path,
+            quirks,
+            platform,
+            fonts,
+            cpu_hz,
+            timing,
+            seed,
+            #[cfg(feature = "sdl")]
+            rewind_seconds,
+            #[cfg(feature = "sdl")]
+            debug,
+            #[cfg(feature = "sdl")]
+            audio,
+            #[cfg(feature = "sdl")]
+            audio_output,
+            record_path,
+            play_path,
+            headless,
+            dump_path,
+            use_database,
+            memory_size,
+            stack_depth,
+            quirks_given,
+            speed_given,
+            platform_given,
+        };
+        options.set_platform(platform);
+        Ok(options)
+    }
+
+    /// Takes on the quirks, speed and platform the database recommends for a ROM, unless they were given on the
+    /// command line.
+    pub fn apply_rom_info(&mut self, info: &RomInfo) {
+        if let Some(quirks) = info.quirks.filter(|_| !self.quirks_given) {
+            self.quirks = quirks;
+        }
+        if let Some(platform) = info.layout {
+            self.suggest_platform(platform);
+        }
+        if let Some(ipf) = info.ipf.filter(|_| !self.speed_given) {
+            self.cpu_hz = ipf * FRAME_RATE;
+            if let Some(settings) = &mut self.headless {
+                settings.cpu_hz = self.cpu_hz;
+            }
+        }
+    }
+
+    /// Takes on a platform worked out from the ROM, unless one was given on the command line.
+    pub fn suggest_platform(&mut self, platform: Platform) {
+        if !self.platform_given {
+            self.set_platform(platform);
+        }
+    }
+
+    /// Checks the fonts and the ROM fit in the platform's memory, either side of the load address.
+    pub fn check_layout(&self, rom_size: usize) -> Result<(), String> {
+        let load_address = self.platform.load_address as usize;
+        if self.fonts.address as usize + Fonts::SIZE > load_address {
+            return Err(format!(
+                "The fonts take {:#x} bytes, so must start by {:#x} to end before the program.",
+                Fonts::SIZE,
+                load_address.saturating_sub(Fonts::SIZE)
+            ));
+        }
+        if rom_size > self.platform.max_rom_size() {
+            return Err(format!(
+                "The ROM is {} bytes, larger than the {} bytes of memory above {:#x}.",
+                rom_size,
+                self.platform.max_rom_size(),
+                load_address
+            ));
+        }
+        Ok(())
+    }
+
+    /// Uses a platform with any memory size and stack depth given on the command line.
+    fn set_platform(&mut self, platform: Platform) {
+        self.platform = Platform {
+            memory_size: self.memory_size.unwrap_or(platform.memory_size),
+            stack_depth: self.stack_depth.unwrap_or(platform.stack_depth),
+            ..platform
+        };
+    }
+}
+
+/// Arguments to the asm, disasm and octo commands: a file to read, where to write the result and the platform
+/// whose load address programs are placed at.
+pub struct ToolOptions {
+    pub path: String,
+    pub output: Option<String>,
+    pub platform: Platform,
+}
+
+impl ToolOptions {
+    /// Parses the arguments following the command's name. usage is shown when they make no sense.
+    pub fn parse(args: &[String], usage: &str, takes_output: bool) -> Result<ToolOptions, String> {
+        let mut path = None;
+        let mut output = None;
+        let mut platform = Platform::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" if takes_output => output = Some(args.next().ok_or(usage)?.clone()),
+                "--platform" => {
+                    let name = args.next().ok_or("--platform requires a name.")?;
+                    platform = Platform::from_name(name)
+                        .ok_or_else(|| format!("Unknown platform '{}'.", name))?;
+                }
+                _ if arg.starts_with('-') || path.is_some() => return Err(usage.to_string()),
+                _ => path = Some(arg.clone()),
+            }
+        }
+        Ok(ToolOptions {
+            path: path.ok_or(usage)?,
+            output,
+            platform,
+        })
+    }
+
+    /// Where to write the result: the output path if one was given, otherwise next to the input with a .ch8
+    /// extension.
+    pub fn output_path(&self) -> PathBuf {
+        match &self.output {
+            Some(output) => PathBuf::from(output),
+            None => Path::new(&self.path).with_extension("ch8"),
+        }
+    }
+}
+
+fn parse_address(option: &str, value: Option<&String>) -> Result<u16, String> {
+    let addr = value.ok_or_else(|| format!("{} requires an address.", option))?;
+    let digits = addr.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address '{}'.", addr))
+}
+
+fn parse_number<T: FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a number.", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, not '{}'.", option, value))
+}
+
+/// Parses a count of something there has to be at least one of.
+fn parse_nonzero(option: &str, value: Option<&String>) -> Result<u32, String> {
+    match parse_number(option, value)? {
+        0 => Err(format!("{} must be at least 1.", option)),
+        count => Ok(count),
+    }
+}
@@ -0,0 +1,139 @@
+use crate::error::StateError;
+use crate::framebuffer::{HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+use crate::state::{StateReader, StateWriter};
+
+/// The machine a program was written for: how much memory it had, where programs went and how deep subroutines
+/// could nest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Platform {
+    pub memory_size: usize,      // 4 KB or 64 KB.
+    pub load_address: u16,       // Where the ROM is copied to.
+    pub start_address: u16,      // Where execution begins.
+    pub stack_depth: usize,      // How many return addresses 2nnn can push.
+    pub display: (usize, usize), // The resolution at power on.
+}
+
+impl Platform {
+    /// The COSMAC VIP, whose interpreter kept 12 return addresses.
+    pub const COSMAC_VIP: Platform = Platform {
+        memory_size: 0x1000,
+        load_address: 0x200,
+        start_address: 0x200,
+        stack_depth: 12,
+        display: (LORES_WIDTH, LORES_HEIGHT),
+    };
+
+    /// The COSMAC VIP running CHIP-8 with a two page, 64x64 display. Hires ROMs start with a patch to the
+    /// interpreter, which is emulated by skipping straight past it to 0x2C0.
+    pub const HIRES_VIP: Platform = Platform {
+        memory_size: 0x1000,
+        load_address: 0x200,
+        start_address: 0x2C0,
+        stack_depth: 12,
+        display: (LORES_WIDTH, HIRES_HEIGHT),
+    };
+
+    /// The ETI-660, whose interpreter took up memory up to 0x600 and drew a 64x48 display.
+    pub const ETI_660: Platform = Platform {
+        memory_size: 0x1000,
+        load_address: 0x600,
+        start_address: 0x600,
+        stack_depth: 16,
+        display: (LORES_WIDTH, 48),
+    };
+
+    /// CHIP-48 and SUPER-CHIP on the HP-48 calculators.
+    pub const HP_48: Platform = Platform {
+        memory_size: 0x1000,
+        load_address: 0x200,
+        start_address: 0x200,
+        stack_depth: 16,
+        display: (LORES_WIDTH, LORES_HEIGHT),
+    };
+
+    /// XO-CHIP as implemented by Octo, and the default: enough room for anything.
+    pub const XO_CHIP: Platform = Platform {
+        memory_size: 0x10000,
+        load_address: 0x200,
+        start_address: 0x200,
+        stack_depth: 16,
+        display: (LORES_WIDTH, LORES_HEIGHT),
+    };
+
+    /// Looks up a named profile, as given on the command line.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name.to_lowercase().as_str() {
+            "vip" | "cosmac-vip" | "chip-8" | "chip8" => Some(Platform::COSMAC_VIP),
+            "hires" | "hires-vip" | "chip-8-hires" => Some(Platform::HIRES_VIP),
+            "eti660" | "eti-660" => Some(Platform::ETI_660),
+            "hp48" | "hp-48" | "chip48" | "chip-48" | "schip" | "super-chip" | "superchip" => {
+                Some(Platform::HP_48)
+            }
+            "xo-chip" | "xochip" | "modern" => Some(Platform::XO_CHIP),
+            _ => None,
+        }
+    }
+
+    /// The largest ROM that fits between the load address and the end of memory.
+    pub fn max_rom_size(&self) -> usize {
+        self.memory_size - self.load_address as usize
+    }
+
+    /// Writes the platform in the form read by read_from.
+    pub fn write_to(&self, state: &mut StateWriter) {
+        state.put_u32(self.memory_size as u32);
+        state.put_u16(self.load_address);
+        state.put_u16(self.start_address);
+        state.put_u8(self.stack_depth as u8);
+        state.put_u16(self.display.0 as u16);
+        state.put_u16(self.display.1 as u16);
+    }
+
+    pub fn read_from(state: &mut StateReader) -> Result<Platform, StateError> {
+        let platform = Platform {
+            memory_size: state.get_u32()? as usize,
+            load_address: state.get_u16()?,
+            start_address: state.get_u16()?,
+            stack_depth: state.get_u8()? as usize,
+            display: (state.get_u16()? as usize, state.get_u16()? as usize),
+        };
+        if platform.memory_size != 0x1000 && platform.memory_size != 0x10000 {
+            return Err(StateError::Invalid("memory size"));
+        }
+        if platform.load_address as usize >= platform.memory_size || platform.stack_depth == 0 {
+            return Err(StateError::Invalid("platform"));
+        }
+        let (width, height) = platform.display;
+        if width == 0 || height == 0 || width > HIRES_WIDTH || height > HIRES_HEIGHT {
+            return Err(StateError::Invalid("resolution"));
+        }
+        Ok(platform)
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Platform {
+        Platform::XO_CHIP
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn platforms_round_trip_through_states() {
+        let mut state = StateWriter::new();
+        Platform::ETI_660.write_to(&mut state);
+        let data = state.finish();
+        let mut state = StateReader::new(&data).unwrap();
+        assert_eq!(Platform::read_from(&mut state), Ok(Platform::ETI_660));
+    }
+
+    #[test]
+    fn roms_fit_above_the_interpreter() {
+        assert_eq!(Platform::COSMAC_VIP.max_rom_size(), 0xE00);
+        assert_eq!(Platform::ETI_660.max_rom_size(), 0xA00);
+        assert_eq!(Platform::XO_CHIP.max_rom_size(), 0xFE00);
+    }
+}
@@ -1,81 +1,289 @@
-use rand::Rng;
-use std::time;
-
-const FONT: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+#![allow(non_snake_case)]
+
+use crate::audio::Sound;
+use crate::error::{ExecutionError, StateError};
+use crate::font::Fonts;
+use crate::framebuffer::{Framebuffer, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+use crate::platform::Platform;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::Random;
+use crate::state::{StateReader, StateWriter};
+use crate::timing::vip_cycles;
+use std::ops::Range;
+
+/// The largest ROM any platform has room for.
+pub const MAX_ROM_SIZE: usize = 0x10000 - 0x200;
 
 pub struct Processor {
-    memory: [u8; 4096],             // 4KB of memory.
-    v: [u8; 16],                    // 16 8-bit general purpose registers. V0 to VF
-    i: u16,                         // 16-bit I register.
-    sound_timer: u8,                // 8-bit sound timer.
-    delay_timer: u8,                // 8-bit delay timer.
-    pc: u16,                        // 16-bit program counter.
-    sp: u8,                         // 16-bit stack pointer.
-    stack: [u16; 16],               // 16 16-bit value stack.
-    vram: [[u8; 64]; 32],           // 64x32 pixel monitor.
-    prev_delay_tick: time::Instant, // The time at which the prev_delay_tick occured.
+    memory: Vec<u8>,                 // 4KB, or 64KB for XO-CHIP.
+    v: [u8; 16],                     // 16 8-bit general purpose registers. V0 to VF
+    i: u16,                          // 16-bit I register.
+    sound_timer: u8,                 // 8-bit sound timer.
+    delay_timer: u8,                 // 8-bit delay timer.
+    pc: u16,                         // 16-bit program counter.
+    sp: u8,                          // 16-bit stack pointer.
+    stack: Vec<u16>,                 // As many 16-bit values as the platform's stack depth.
+    vram: Framebuffer, // 64x32 or 128x64 pixel monitor, or the platform's own resolution.
+    rpl: [u8; 16],     // HP-48 RPL user flags used by Fx75 and Fx85.
+    planes: u8,        // Bitmask of the XO-CHIP bitplanes being drawn to.
+    audio_pattern: Option<[u8; 16]>, // XO-CHIP 128 bit audio sample buffer, once loaded by F002.
+    pitch: u8,         // XO-CHIP audio playback rate register.
+    quirks: Quirks,    // Interpreter specific behaviour of ambiguous opcodes.
+    random: Random,    // Source of Cxkk's random bytes.
+    fonts: Fonts,      // The hex digit sprites Fx29 and Fx30 point at.
+    platform: Platform, // The memory layout and stack depth of the machine being emulated.
 }
 
 pub struct CycleResult {
-    pub video_out: [[u8; 64]; 32], // 64x32 pixel monitor.
     pub video_changed: bool,
+    pub exited: bool,           // The program executed 00FD.
+    pub vip_cycles: u32,        // Machine cycles the instruction takes on the COSMAC VIP.
+    pub waits_for_vblank: bool, // Dxyn, which the VIP only starts after a display interrupt.
 }
 
 impl Processor {
-    pub fn new() -> Processor {
-        let mut memory: [u8; 4096] = [0x0; 4096];
-        memory[0x50..0xa0].copy_from_slice(&FONT);
+    pub fn new(quirks: Quirks) -> Processor {
+        Processor::for_platform(quirks, Platform::default())
+    }
+
+    /// A processor with the memory, stack and display of a particular machine.
+    pub fn for_platform(quirks: Quirks, platform: Platform) -> Processor {
+        let mut memory = vec![0x0; platform.memory_size];
+        let fonts = Fonts::default();
+        fonts.load_into(&mut memory);
 
         Processor {
-            memory: memory,
+            memory,
             v: [0x0; 16],
             i: 0x0,
             sound_timer: 0x0,
             delay_timer: 0x0,
-            pc: 0x200,
+            pc: platform.start_address,
             sp: 0x0,
-            stack: [0x0; 16],
-            vram: [[0x0; 64]; 32],
-            prev_delay_tick: time::Instant::now(),
+            stack: vec![0x0; platform.stack_depth],
+            vram: Framebuffer::new(platform.display.0, platform.display.1),
+            rpl: [0x0; 16],
+            planes: 0x1,
+            audio_pattern: None,
+            pitch: 64,
+            quirks,
+            random: Random::new(rand::random()),
+            fonts,
+            platform,
         }
     }
 
-    pub fn load_cartridge(&mut self, cartridge: &[u8; 3584]) {
-        self.memory[0x200..].clone_from_slice(&cartridge[..]);
+    /// Replaces the hex digit sprites with other fonts, which must fit in memory. Done before loading a ROM, as
+    /// they may overlap it.
+    pub fn set_fonts(&mut self, fonts: Fonts) {
+        fonts.load_into(&mut self.memory);
+        self.fonts = fonts;
     }
 
-    pub fn cycle(&mut self, keyboard_input: [bool; 16]) -> CycleResult {
-        if self.delay_timer > 0 {
-            let now = time::Instant::now();
-            let elapsed = now - self.prev_delay_tick;
-            // decrease delay time every 1/60th of a second.
-            if elapsed.as_millis() > 16 {
-                self.delay_timer -= 1;
-                self.prev_delay_tick = now;
-            }
+    /// Replaces the random number generator, e.g. with a seeded one so a run can be repeated exactly.
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
+    }
+
+    /// Copies a ROM into memory at the platform's load address. It must fit, see Platform::max_rom_size.
+    pub fn load_cartridge(&mut self, cartridge: &[u8]) {
+        let address = self.platform.load_address as usize;
+        self.memory[address..address + cartridge.len()].copy_from_slice(cartridge);
+    }
+
+    /// The RPL user flags, which persist between runs on a real HP-48.
+    pub fn rpl_flags(&self) -> [u8; 16] {
+        self.rpl
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; 16]) {
+        self.rpl = flags;
+    }
+
+    /// What the speaker should currently be playing.
+    pub fn sound(&self) -> Sound {
+        Sound {
+            playing: self.sound_timer > 0,
+            pattern: self.audio_pattern,
+            pitch: self.pitch,
         }
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The general purpose registers V0 to VF.
+    pub fn v(&self) -> [u8; 16] {
+        self.v
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
 
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// The return addresses of the subroutines currently being executed, outermost first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// The 64x32 or 128x64 pixel monitor, or the platform's own resolution.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.vram
+    }
+
+    /// Decreases the delay and sound timers. Must be called once per 60 Hz frame.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// Captures the complete machine state, including the platform, the active quirks and video mode.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        self.platform.write_to(&mut state);
+        state.put_bytes(&self.memory);
+        state.put_bytes(&self.v);
+        state.put_u16(self.i);
+        state.put_u8(self.sound_timer);
+        state.put_u8(self.delay_timer);
+        state.put_u16(self.pc);
+        state.put_u8(self.sp);
+        for &addr in self.stack.iter() {
+            state.put_u16(addr);
+        }
+        state.put_u16(self.vram.width() as u16);
+        state.put_u16(self.vram.height() as u16);
+        state.put_bytes(self.vram.pixels());
+        state.put_bytes(&self.rpl);
+        state.put_u8(self.planes);
+        state.put_bool(self.audio_pattern.is_some());
+        state.put_bytes(&self.audio_pattern.unwrap_or([0x0; 16]));
+        state.put_u8(self.pitch);
+        self.quirks.write_to(&mut state);
+        self.random.write_to(&mut state);
+        self.fonts.write_to(&mut state);
+        state.finish()
+    }
+
+    /// Restores a state captured by save_state. The processor is left untouched if the state is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data)?;
+        let platform = Platform::read_from(&mut state)?;
+        let memory = state.get_bytes()?;
+        if memory.len() != platform.memory_size {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let v = state.get_array()?;
+        let i = state.get_u16()?;
+        let sound_timer = state.get_u8()?;
+        let delay_timer = state.get_u8()?;
+        let pc = state.get_u16()?;
+        let sp = state.get_u8()?;
+        if sp as usize > platform.stack_depth {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let mut stack = vec![0x0; platform.stack_depth];
+        for addr in stack.iter_mut() {
+            *addr = state.get_u16()?;
+        }
+        let width = state.get_u16()? as usize;
+        let height = state.get_u16()? as usize;
+        if (width, height) != (LORES_WIDTH, LORES_HEIGHT)
+            && (width, height) != (HIRES_WIDTH, HIRES_HEIGHT)
+            && (width, height) != platform.display
+        {
+            return Err(StateError::Invalid("resolution"));
+        }
+        let vram = Framebuffer::from_pixels(width, height, state.get_bytes()?.to_vec())
+            .ok_or(StateError::Invalid("framebuffer"))?;
+        let rpl = state.get_array()?;
+        let planes = state.get_u8()?;
+        let has_pattern = state.get_bool()?;
+        let pattern = state.get_array()?;
+        let pitch = state.get_u8()?;
+        let quirks = Quirks::read_from(&mut state)?;
+        let random = Random::read_from(&mut state)?;
+        let fonts = Fonts::read_from(&mut state)?;
+        if fonts.address as usize + Fonts::SIZE > platform.memory_size {
+            return Err(StateError::Invalid("font address"));
+        }
+
+        self.memory = memory.to_vec();
+        self.v = v;
+        self.i = i;
+        self.sound_timer = sound_timer;
+        self.delay_timer = delay_timer;
+        self.pc = pc;
+        self.sp = sp;
+        self.stack = stack;
+        self.vram = vram;
+        self.rpl = rpl;
+        self.planes = planes;
+        self.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        self.pitch = pitch;
+        self.quirks = quirks;
+        self.random = random;
+        self.fonts = fonts;
+        self.platform = platform;
+        Ok(())
+    }
+
+    /// V0 to VF, I, PC, SP, the stack and the timers, formatted for diagnostics.
+    pub fn register_dump(&self) -> String {
+        let registers: Vec<String> = self
+            .v
+            .iter()
+            .enumerate()
+            .map(|(index, value)| format!("V{:X}={:02X}", index, value))
+            .collect();
+        let stack: Vec<String> = self.stack[..self.sp as usize]
+            .iter()
+            .map(|addr| format!("{:03X}", addr))
+            .collect();
+        format!(
+            "{}\nI={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}\nStack=[{}]",
+            registers.join(" "),
+            self.i,
+            self.pc,
+            self.sp,
+            self.delay_timer,
+            self.sound_timer,
+            stack.join(" ")
+        )
+    }
+
+    pub fn cycle(&mut self, keyboard_input: [bool; 16]) -> Result<CycleResult, ExecutionError> {
         // Fetch
-        let instruction: u16 = (self.memory[self.pc as usize] as u16) << 8
-            | self.memory[(self.pc + 1) as usize] as u16;
-        self.pc += 2;
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Err(ExecutionError::MemoryOutOfBounds {
+                pc: self.pc,
+                addr: self.memory.len(),
+            });
+        }
+        let instruction =
+            (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
+        self.pc = self.pc.wrapping_add(2);
 
         // Decode
         let inst_id = (instruction & 0xf000) >> 12;
@@ -85,18 +293,57 @@ impl Processor {
         let nn: u8 = (instruction & 0x00ff) as u8;
         let n: u8 = (instruction & 0x000f) as u8;
         let mut vram_changed = false;
+        let mut exited = false;
+        let vip_cycles = vip_cycles(instruction, &self.v, keyboard_input);
+        let invalid = ExecutionError::InvalidOpcode {
+            pc: self.pc.wrapping_sub(2),
+            opcode: instruction,
+        };
         // Execute
         match inst_id {
             0x0 => match nnn {
-                0x0E0 => inst_00E0(self),
-                0x0EE => inst_00EE(self),
+                0x0C0..=0x0CF => {
+                    inst_00Cn(self, n);
+                    vram_changed = true;
+                }
+                0x0D0..=0x0DF => {
+                    inst_00Dn(self, n);
+                    vram_changed = true;
+                }
+                0x0E0 => {
+                    inst_00E0(self);
+                    vram_changed = true;
+                }
+                0x0EE => inst_00EE(self)?,
+                0x0FB => {
+                    inst_00FB(self);
+                    vram_changed = true;
+                }
+                0x0FC => {
+                    inst_00FC(self);
+                    vram_changed = true;
+                }
+                0x0FD => exited = true,
+                0x0FE => {
+                    inst_00FE(self);
+                    vram_changed = true;
+                }
+                0x0FF => {
+                    inst_00FF(self);
+                    vram_changed = true;
+                }
                 _ => inst_0nnn(self, nnn),
             },
             0x1 => inst_1nnn(self, nnn),
-            0x2 => inst_2nnn(self, nnn),
-            0x3 => inst_3xkk(self, x, nn),
-            0x4 => inst_4xkk(self, x, nn),
-            0x5 => inst_5xy0(self, x, y),
+            0x2 => inst_2nnn(self, nnn)?,
+            0x3 => inst_3xkk(self, x, nn)?,
+            0x4 => inst_4xkk(self, x, nn)?,
+            0x5 => match n {
+                0x0 => inst_5xy0(self, x, y)?,
+                0x2 => inst_5xy2(self, x, y)?,
+                0x3 => inst_5xy3(self, x, y)?,
+                _ => return Err(invalid),
+            },
             0x6 => inst_6xkk(self, x, nn),
             0x7 => inst_7xkk(self, x, nn),
             0x8 => match n {
@@ -109,59 +356,148 @@ impl Processor {
                 0x6 => inst_8xy6(self, x, y),
                 0x7 => inst_8xy7(self, x, y),
                 0xE => inst_8xyE(self, x, y),
-                _ => (),
+                _ => return Err(invalid),
             },
-            0x9 => inst_9xy0(self, x, y),
+            0x9 => inst_9xy0(self, x, y)?,
             0xA => inst_Annn(self, nnn),
             0xB => inst_Bnnn(self, nnn),
             0xC => inst_Cxkk(self, x, nn),
             0xD => {
-                vram_changed = inst_Dxyn(self, x as usize, y as usize, n as usize);
+                vram_changed = inst_Dxyn(self, x as usize, y as usize, n as usize)?;
             }
             0xE => match nn {
-                0x9E => inst_Ex9E(self, x, keyboard_input),
-                0xA1 => inst_ExA1(self, x, keyboard_input),
-                _ => (),
+                0x9E => inst_Ex9E(self, x, keyboard_input)?,
+                0xA1 => inst_ExA1(self, x, keyboard_input)?,
+                _ => return Err(invalid),
             },
             0xF => match nn {
+                0x00 if x == 0x0 => inst_F000(self)?,
+                0x01 => inst_Fn01(self, x),
+                0x02 if x == 0x0 => inst_F002(self)?,
                 0x07 => inst_Fx07(self, x),
                 0x0A => inst_Fx0A(self, x, keyboard_input),
                 0x15 => inst_Fx15(self, x),
                 0x18 => inst_Fx18(self, x),
                 0x1E => inst_Fx1E(self, x),
                 0x29 => inst_Fx29(self, x),
-                0x33 => inst_Fx33(self, x),
-                0x55 => inst_Fx55(self, x),
-                0x65 => inst_Fx65(self, x),
-                _ => (),
+                0x30 => inst_Fx30(self, x),
+                0x33 => inst_Fx33(self, x)?,
+                0x3A => inst_Fx3A(self, x),
+                0x55 => inst_Fx55(self, x)?,
+                0x65 => inst_Fx65(self, x)?,
+                0x75 => inst_Fx75(self, x),
+                0x85 => inst_Fx85(self, x),
+                _ => return Err(invalid),
             },
-            _ => (),
+            _ => return Err(invalid),
         }
 
-        return CycleResult {
-            video_out: self.vram,
+        Ok(CycleResult {
             video_changed: vram_changed,
-        };
+            exited,
+            vip_cycles,
+            waits_for_vblank: inst_id == 0xD,
+        })
     }
 }
 
+/// The len bytes of memory starting at addr, or an error if they run past the end of memory.
+/// Instructions check their accesses before they move the program counter, so the faulting instruction is at PC - 2.
+fn memory_range(cpu: &Processor, addr: usize, len: usize) -> Result<Range<usize>, ExecutionError> {
+    if addr + len > cpu.memory.len() {
+        return Err(ExecutionError::MemoryOutOfBounds {
+            pc: cpu.pc.wrapping_sub(2),
+            addr: addr.max(cpu.memory.len()),
+        });
+    }
+    Ok(addr..addr + len)
+}
+
+/// Reads the big endian 16-bit word at addr.
+fn read_word(cpu: &Processor, addr: u16) -> Result<u16, ExecutionError> {
+    let range = memory_range(cpu, addr as usize, 2)?;
+    let bytes = &cpu.memory[range];
+    Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+/// Moves the program counter past the next instruction.
+/// XO-CHIP's F000 nnnn is four bytes long, so conditional skips have to step over all of it.
+fn skip_instruction(cpu: &mut Processor) -> Result<(), ExecutionError> {
+    let length = if read_word(cpu, cpu.pc)? == 0xF000 {
+        4
+    } else {
+        2
+    };
+    cpu.pc = cpu.pc.wrapping_add(length);
+    Ok(())
+}
+
 /// 0nnn - SYS addr
 /// Jump to a machine code routine at nnn.
 /// This instruction is only used on the old computers on which Chip-8 was originally implemented. It is ignored by modern interpreters.
-fn inst_0nnn(cpu: &mut Processor, addr: u16) {}
+fn inst_0nnn(_cpu: &mut Processor, _addr: u16) {}
+
+/// 00Cn - SCD nibble
+/// Scroll the display down by n pixels.
+/// SUPER-CHIP only. The rows scrolled in at the top are blank.
+fn inst_00Cn(cpu: &mut Processor, n: u8) {
+    cpu.vram.scroll_down(n as usize, cpu.planes);
+}
+
+/// 00Dn - SCU nibble
+/// Scroll the display up by n pixels.
+/// XO-CHIP only. The rows scrolled in at the bottom are blank.
+fn inst_00Dn(cpu: &mut Processor, n: u8) {
+    cpu.vram.scroll_up(n as usize, cpu.planes);
+}
 
 /// 00E0 - CLS
 /// Clear the display.
+/// On XO-CHIP only the selected bitplanes are cleared.
 fn inst_00E0(cpu: &mut Processor) {
-    cpu.vram = [[0x0; 64]; 32];
+    cpu.vram.clear(cpu.planes);
 }
 
 /// 00EE - RET
 /// Return from a subroutine.
-/// The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack pointer.
-fn inst_00EE(cpu: &mut Processor) {
-    cpu.pc = cpu.stack[cpu.sp as usize];
+/// The interpreter subtracts 1 from the stack pointer, then sets the program counter to the address at the top of the stack.
+fn inst_00EE(cpu: &mut Processor) -> Result<(), ExecutionError> {
+    if cpu.sp == 0 {
+        return Err(ExecutionError::StackUnderflow {
+            pc: cpu.pc.wrapping_sub(2),
+        });
+    }
     cpu.sp -= 1;
+    cpu.pc = cpu.stack[cpu.sp as usize];
+    Ok(())
+}
+
+/// 00FB - SCR
+/// Scroll the display right by 4 pixels.
+/// SUPER-CHIP only. The columns scrolled in on the left are blank.
+fn inst_00FB(cpu: &mut Processor) {
+    cpu.vram.scroll_right(4, cpu.planes);
+}
+
+/// 00FC - SCL
+/// Scroll the display left by 4 pixels.
+/// SUPER-CHIP only. The columns scrolled in on the right are blank.
+fn inst_00FC(cpu: &mut Processor) {
+    cpu.vram.scroll_left(4, cpu.planes);
+}
+
+/// 00FE - LOW
+/// Disable high resolution graphics mode.
+/// SUPER-CHIP only. The display returns to 64x32 and is cleared.
+fn inst_00FE(cpu: &mut Processor) {
+    cpu.vram = Framebuffer::new(LORES_WIDTH, LORES_HEIGHT);
+}
+
+/// 00FF - HIGH
+/// Enable high resolution graphics mode.
+/// SUPER-CHIP only. The display switches to 128x64 and is cleared.
+fn inst_00FF(cpu: &mut Processor) {
+    cpu.vram = Framebuffer::new(HIRES_WIDTH, HIRES_HEIGHT);
 }
 
 /// 1nnn - JP addr
@@ -173,41 +509,82 @@ fn inst_1nnn(cpu: &mut Processor, nnn: u16) {
 
 /// 2nnn - CALL addr
 /// Call subroutine at nnn.
-/// The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC is then set to nnn.
-fn inst_2nnn(cpu: &mut Processor, nnn: u16) {
-    cpu.sp += 1;
+/// The interpreter puts the current PC on the top of the stack, then increments the stack pointer. The PC is then set to nnn.
+fn inst_2nnn(cpu: &mut Processor, nnn: u16) -> Result<(), ExecutionError> {
+    if cpu.sp as usize == cpu.stack.len() {
+        return Err(ExecutionError::StackOverflow {
+            pc: cpu.pc.wrapping_sub(2),
+        });
+    }
     cpu.stack[cpu.sp as usize] = cpu.pc;
+    cpu.sp += 1;
     cpu.pc = nnn;
+    Ok(())
 }
 
 /// 3xkk - SE Vx, byte
 /// Skip next instruction if Vx = kk.
 /// The interpreter compares register Vx to kk, and if they are equal, increments the program counter by 2.
-fn inst_3xkk(cpu: &mut Processor, x: u8, kk: u8) {
+fn inst_3xkk(cpu: &mut Processor, x: u8, kk: u8) -> Result<(), ExecutionError> {
     let x = x as usize;
-    if cpu.v[x as usize] == kk {
-        cpu.pc += 2;
+    if cpu.v[x] == kk {
+        skip_instruction(cpu)?;
     }
+    Ok(())
 }
 
 /// 4xkk - SNE Vx, byte
 /// Skip next instruction if Vx != kk.
 /// The interpreter compares register Vx to kk, and if they are not equal, increments the program counter by 2.
-fn inst_4xkk(cpu: &mut Processor, x: u8, kk: u8) {
+fn inst_4xkk(cpu: &mut Processor, x: u8, kk: u8) -> Result<(), ExecutionError> {
     let x = x as usize;
     if cpu.v[x] != kk {
-        cpu.pc += 2;
+        skip_instruction(cpu)?;
     }
+    Ok(())
 }
 
 /// 5xy0 - SE Vx, Vy
 /// Skip next instruction if Vx = Vy.
 /// The interpreter compares register Vx to register Vy, and if they are equal, increments the program counter by 2.
-fn inst_5xy0(cpu: &mut Processor, x: u8, y: u8) {
+fn inst_5xy0(cpu: &mut Processor, x: u8, y: u8) -> Result<(), ExecutionError> {
     let x = x as usize;
     let y = y as usize;
     if cpu.v[x] == cpu.v[y] {
-        cpu.pc += 2;
+        skip_instruction(cpu)?;
+    }
+    Ok(())
+}
+
+/// 5xy2 - LD [I], Vx - Vy
+/// Store registers Vx through Vy in memory starting at location I.
+/// XO-CHIP only. The registers are stored in reverse order if x > y. I is not changed.
+fn inst_5xy2(cpu: &mut Processor, x: u8, y: u8) -> Result<(), ExecutionError> {
+    let range = memory_range(cpu, cpu.i as usize, x.abs_diff(y) as usize + 1)?;
+    for (address, register) in range.zip(register_range(x, y)) {
+        cpu.memory[address] = cpu.v[register];
+    }
+    Ok(())
+}
+
+/// 5xy3 - LD Vx - Vy, [I]
+/// Read registers Vx through Vy from memory starting at location I.
+/// XO-CHIP only. The registers are loaded in reverse order if x > y. I is not changed.
+fn inst_5xy3(cpu: &mut Processor, x: u8, y: u8) -> Result<(), ExecutionError> {
+    let range = memory_range(cpu, cpu.i as usize, x.abs_diff(y) as usize + 1)?;
+    for (address, register) in range.zip(register_range(x, y)) {
+        cpu.v[register] = cpu.memory[address];
+    }
+    Ok(())
+}
+
+/// The registers from x to y inclusive, counting down when x > y.
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
     }
 }
 
@@ -241,20 +618,28 @@ fn inst_8xy0(cpu: &mut Processor, x: u8, y: u8) {
 /// Set Vx = Vx OR Vy.
 /// Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx. A bitwise OR compares the corrseponding
 /// bits from two values, and if either bit is 1, then the same bit in the result is also 1. Otherwise, it is 0.
+/// With the logic_resets_vf quirk VF is then set to 0.
 fn inst_8xy1(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
     let y = y as usize;
-    cpu.v[x] = cpu.v[x] | cpu.v[y];
+    cpu.v[x] |= cpu.v[y];
+    if cpu.quirks.logic_resets_vf {
+        cpu.v[0xF] = 0;
+    }
 }
 
 /// 8xy2 - AND Vx, Vy
 /// Set Vx = Vx AND Vy.
 /// Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx. A bitwise AND compares the corrseponding
 /// bits from two values, and if both bits are 1, then the same bit in the result is also 1. Otherwise, it is 0.
+/// With the logic_resets_vf quirk VF is then set to 0.
 fn inst_8xy2(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
     let y = y as usize;
-    cpu.v[x] = cpu.v[x] & cpu.v[y];
+    cpu.v[x] &= cpu.v[y];
+    if cpu.quirks.logic_resets_vf {
+        cpu.v[0xF] = 0;
+    }
 }
 
 /// 8xy3 - XOR Vx, Vy
@@ -262,71 +647,91 @@ fn inst_8xy2(cpu: &mut Processor, x: u8, y: u8) {
 /// Performs a bitwise exclusive OR on the values of Vx and Vy, then stores the result in Vx. An exclusive OR compares the
 /// corrseponding bits from two values, and if the bits are not both the same, then the corresponding bit in the result is set to 1.
 /// Otherwise, it is 0.
+/// With the logic_resets_vf quirk VF is then set to 0.
 fn inst_8xy3(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
     let y = y as usize;
     cpu.v[x] ^= cpu.v[y];
+    if cpu.quirks.logic_resets_vf {
+        cpu.v[0xF] = 0;
+    }
 }
 
 /// 8xy4 - ADD Vx, Vy
 /// Set Vx = Vx + Vy, set VF = carry.
 /// The values of Vx and Vy are added together. If the result is greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0. Only the
 /// lowest 8 bits of the result are kept, and stored in Vx.
+/// VF is written last, so when it is also Vx it ends up holding the flag. The same goes for 8xy5 to 8xyE.
 fn inst_8xy4(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
     let y = y as usize;
-    let result: u16 = (cpu.v[x] as u32 + cpu.v[y] as u32) as u16;
-    cpu.v[0xF] = if result > 255 { 1 } else { 0 };
-    cpu.v[x] = result as u8;
+    let (result, carry) = cpu.v[x].overflowing_add(cpu.v[y]);
+    cpu.v[x] = result;
+    cpu.v[0xF] = carry as u8;
 }
 
 /// 8xy5 - SUB Vx, Vy
 /// Set Vx = Vx - Vy, set VF = NOT borrow.
-/// If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
+/// If Vx >= Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
 fn inst_8xy5(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
     let y = y as usize;
-    cpu.v[0xF] = if cpu.v[x] > cpu.v[y] { 1 } else { 0 };
-    cpu.v[x] = cpu.v[x].wrapping_sub(cpu.v[y]);
+    let (result, borrow) = cpu.v[x].overflowing_sub(cpu.v[y]);
+    cpu.v[x] = result;
+    cpu.v[0xF] = !borrow as u8;
 }
 
 /// 8xy6 - SHR Vx {, Vy}
 /// Set Vx = Vx SHR 1.
 /// If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
+/// With the shift_uses_vy quirk Vy is shifted instead and the result stored in Vx.
 fn inst_8xy6(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
-    cpu.v[0xF] = if (cpu.v[x] & 0x1) == 0x1 { 1 } else { 0 };
-    cpu.v[x] /= 2;
+    let source = if cpu.quirks.shift_uses_vy {
+        cpu.v[y as usize]
+    } else {
+        cpu.v[x]
+    };
+    cpu.v[x] = source >> 1;
+    cpu.v[0xF] = source & 0x1;
 }
 
 /// 8xy7 - SUBN Vx, Vy
 /// Set Vx = Vy - Vx, set VF = NOT borrow.
-/// If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
+/// If Vy >= Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
 fn inst_8xy7(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
     let y = y as usize;
-    cpu.v[0xF] = if cpu.v[x] > cpu.v[y] { 1 } else { 0 };
-    cpu.v[x] = cpu.v[y] - cpu.v[x];
+    let (result, borrow) = cpu.v[y].overflowing_sub(cpu.v[x]);
+    cpu.v[x] = result;
+    cpu.v[0xF] = !borrow as u8;
 }
 
 /// 8xyE - SHL Vx {, Vy}
 /// Set Vx = Vx SHL 1.
 /// If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
+/// With the shift_uses_vy quirk Vy is shifted instead and the result stored in Vx.
 fn inst_8xyE(cpu: &mut Processor, x: u8, y: u8) {
     let x = x as usize;
-    cpu.v[0xF] = if (cpu.v[x] & 0x80) == 0x80 { 1 } else { 0 };
-    cpu.v[x] *= 2;
+    let source = if cpu.quirks.shift_uses_vy {
+        cpu.v[y as usize]
+    } else {
+        cpu.v[x]
+    };
+    cpu.v[x] = source << 1;
+    cpu.v[0xF] = source >> 7;
 }
 
 /// 9xy0 - SNE Vx, Vy
 /// Skip next instruction if Vx != Vy.
 /// The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
-fn inst_9xy0(cpu: &mut Processor, x: u8, y: u8) {
+fn inst_9xy0(cpu: &mut Processor, x: u8, y: u8) -> Result<(), ExecutionError> {
     let x = x as usize;
     let y = y as usize;
     if cpu.v[x] != cpu.v[y] {
-        cpu.pc += 2;
+        skip_instruction(cpu)?;
     }
+    Ok(())
 }
 
 /// Annn - LD I, addr
@@ -339,18 +744,24 @@ fn inst_Annn(cpu: &mut Processor, nnn: u16) {
 /// Bnnn - JP V0, addr
 /// Jump to location nnn + V0.
 /// The program counter is set to nnn plus the value of V0.
+/// With the jump_uses_vx quirk this becomes Bxnn, jumping to xnn plus the value of Vx.
 fn inst_Bnnn(cpu: &mut Processor, nnn: u16) {
-    cpu.pc = nnn + cpu.v[0] as u16;
+    let register = if cpu.quirks.jump_uses_vx {
+        (nnn >> 8) as usize
+    } else {
+        0
+    };
+    cpu.pc = nnn + cpu.v[register] as u16;
 }
 
 /// Cxkk - RND Vx, byte
 /// Set Vx = random byte AND kk.
 /// The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx.
 /// See instruction 8xy2 for more information on AND.
+/// The numbers come from the processor's own generator, so a run with the same seed always gets the same ones.
 fn inst_Cxkk(cpu: &mut Processor, x: u8, kk: u8) {
     let x = x as usize;
-    let mut rng = rand::thread_rng();
-    let number: u8 = rng.gen();
+    let number = cpu.random.next_byte();
     cpu.v[x] = number & kk;
 }
 
@@ -361,42 +772,105 @@ fn inst_Cxkk(cpu: &mut Processor, x: u8, kk: u8) {
 /// is set to 0. If the sprite is positioned so part of it is outside the coordinates of the display, it wraps around to the opposite side
 /// of the screen. See instruction 8xy3 for more information on XOR, and section 2.4, Display, for more information on the Chip-8 screen
 /// and sprites.
-fn inst_Dxyn(cpu: &mut Processor, x: usize, y: usize, n: usize) -> bool {
+/// Without the sprite_wrap quirk only the starting coordinates wrap and the parts of the sprite past the edges are clipped.
+/// SUPER-CHIP: when n is 0 a 16x16 sprite is drawn from 32 bytes, two bytes per row.
+/// XO-CHIP: the sprite is drawn to every selected bitplane, each plane reading the next sprite's worth of bytes after the last.
+fn inst_Dxyn(cpu: &mut Processor, x: usize, y: usize, n: usize) -> Result<bool, ExecutionError> {
+    let (width, height) = if n == 0 { (16, 16) } else { (8, n) };
+    let plane_count = cpu.planes.count_ones() as usize;
+    memory_range(cpu, cpu.i as usize, plane_count * height * width / 8)?;
+    let screen_width = cpu.vram.width();
+    let screen_height = cpu.vram.height();
+    let start_x = cpu.v[x] as usize % screen_width;
+    let start_y = cpu.v[y] as usize % screen_height;
     cpu.v[0x0f] = 0;
     let mut vram_changed = false;
-    for i in 0..n {
-        let y = (cpu.v[y] as usize + i) % 32;
-        for j in 0..8 {
-            let x = (cpu.v[x] as usize + j) % 64;
-            let bit = (cpu.memory[cpu.i as usize + i as usize] >> (7 - j)) & 0x01;
-            cpu.v[0x0f] |= bit & cpu.vram[y][x];
-            let prev_bit = cpu.vram[y][x];
-            cpu.vram[y][x] ^= bit;
-            vram_changed = vram_changed || (prev_bit != cpu.vram[y][x]);
+    let mut address = cpu.i as usize;
+    for plane in 0..2 {
+        let plane_bit = 0x1 << plane;
+        if cpu.planes & plane_bit == 0 {
+            continue;
         }
+        for i in 0..height {
+            let row_address = address + i * width / 8;
+            if !cpu.quirks.sprite_wrap && start_y + i >= screen_height {
+                break;
+            }
+            let y = (start_y + i) % screen_height;
+            let row = if width == 16 {
+                (cpu.memory[row_address] as u16) << 8 | cpu.memory[row_address + 1] as u16
+            } else {
+                (cpu.memory[row_address] as u16) << 8
+            };
+            for j in 0..width {
+                if !cpu.quirks.sprite_wrap && start_x + j >= screen_width {
+                    break;
+                }
+                let x = (start_x + j) % screen_width;
+                if (row >> (15 - j)) & 0x01 == 0 {
+                    continue;
+                }
+                let pixel = cpu.vram.get(x, y);
+                if pixel & plane_bit != 0 {
+                    cpu.v[0x0f] = 1;
+                }
+                cpu.vram.set(x, y, pixel ^ plane_bit);
+                vram_changed = true;
+            }
+        }
+        address += height * width / 8;
     }
 
-    vram_changed
+    Ok(vram_changed)
 }
 
 /// Ex9E - SKP Vx
 /// Skip next instruction if key with the value of Vx is pressed.
 /// Checks the keyboard, and if the key corresponding to the value of Vx is currently in the down position, PC is increased by 2.
-fn inst_Ex9E(cpu: &mut Processor, x: u8, keyboard_input: [bool; 16]) {
+fn inst_Ex9E(cpu: &mut Processor, x: u8, keyboard_input: [bool; 16]) -> Result<(), ExecutionError> {
     let x = x as usize;
-    if keyboard_input[cpu.v[x] as usize] {
-        cpu.pc += 2;
+    if keyboard_input[cpu.v[x] as usize & 0xf] {
+        skip_instruction(cpu)?;
     }
+    Ok(())
 }
 
 /// ExA1 - SKNP Vx
 /// Skip next instruction if key with the value of Vx is not pressed.
 /// Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up position, PC is increased by 2.
-fn inst_ExA1(cpu: &mut Processor, x: u8, keyboard_input: [bool; 16]) {
+fn inst_ExA1(cpu: &mut Processor, x: u8, keyboard_input: [bool; 16]) -> Result<(), ExecutionError> {
     let x = x as usize;
-    if !keyboard_input[cpu.v[x] as usize] {
-        cpu.pc += 2;
+    if !keyboard_input[cpu.v[x] as usize & 0xf] {
+        skip_instruction(cpu)?;
     }
+    Ok(())
+}
+
+/// F000 nnnn - LD I, long addr
+/// Set I = nnnn.
+/// XO-CHIP only. The 16-bit address is read from the two bytes following the instruction, which is skipped over.
+fn inst_F000(cpu: &mut Processor) -> Result<(), ExecutionError> {
+    cpu.i = read_word(cpu, cpu.pc)?;
+    cpu.pc = cpu.pc.wrapping_add(2);
+    Ok(())
+}
+
+/// Fn01 - PLANE n
+/// Select the bitplanes drawn to.
+/// XO-CHIP only. n is a bitmask of the two planes used by 00E0, the scroll instructions and Dxyn.
+fn inst_Fn01(cpu: &mut Processor, n: u8) {
+    cpu.planes = n & 0x3;
+}
+
+/// F002 - AUDIO
+/// Load the audio pattern buffer.
+/// XO-CHIP only. The 16 bytes starting at I are copied into the audio pattern buffer, which is played back one bit at a time.
+fn inst_F002(cpu: &mut Processor) -> Result<(), ExecutionError> {
+    let range = memory_range(cpu, cpu.i as usize, 16)?;
+    let mut pattern = [0x0; 16];
+    pattern.copy_from_slice(&cpu.memory[range]);
+    cpu.audio_pattern = Some(pattern);
+    Ok(())
 }
 
 /// Fx07 - LD Vx, DT
@@ -412,11 +886,9 @@ fn inst_Fx07(cpu: &mut Processor, x: u8) {
 /// All execution stops until a key is pressed, then the value of that key is stored in Vx.
 fn inst_Fx0A(cpu: &mut Processor, x: u8, keyboard_input: [bool; 16]) {
     let x = x as usize;
-    for i in 0..16 {
-        if keyboard_input[i] {
-            cpu.v[x] = i as u8;
-            return;
-        }
+    if let Some(key) = keyboard_input.iter().position(|&pressed| pressed) {
+        cpu.v[x] = key as u8;
+        return;
     }
     // Revert counter back to halt;
     cpu.pc -= 2;
@@ -428,7 +900,6 @@ fn inst_Fx0A(cpu: &mut Processor, x: u8, keyboard_input: [bool; 16]) {
 fn inst_Fx15(cpu: &mut Processor, x: u8) {
     let x = x as usize;
     cpu.delay_timer = cpu.v[x];
-    cpu.prev_delay_tick = time::Instant::now();
 }
 
 /// Fx18 - LD ST, Vx
@@ -444,7 +915,7 @@ fn inst_Fx18(cpu: &mut Processor, x: u8) {
 /// The values of I and Vx are added, and the results are stored in I.
 fn inst_Fx1E(cpu: &mut Processor, x: u8) {
     let x = x as usize;
-    cpu.i += cpu.v[x] as u16;
+    cpu.i = cpu.i.wrapping_add(cpu.v[x] as u16);
 }
 
 /// Fx29 - LD F, Vx
@@ -453,39 +924,1054 @@ fn inst_Fx1E(cpu: &mut Processor, x: u8) {
 /// more information on the Chip-8 hexadecimal font.
 fn inst_Fx29(cpu: &mut Processor, x: u8) {
     let x = x as usize;
-    cpu.i = cpu.v[x] as u16;
+    cpu.i = cpu.fonts.digit(cpu.v[x]);
+}
+
+/// Fx30 - LD HF, Vx
+/// Set I = location of large sprite for digit Vx.
+/// SUPER-CHIP only. The value of I is set to the location of the 8x10 hexadecimal sprite corresponding to the value of Vx.
+fn inst_Fx30(cpu: &mut Processor, x: u8) {
+    let x = x as usize;
+    cpu.i = cpu.fonts.big_digit(cpu.v[x]);
 }
 
 /// Fx33 - LD B, Vx
 /// Store BCD representation of Vx in memory locations I, I+1, and I+2.
 /// The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1,
 /// and the ones digit at location I+2.
-fn inst_Fx33(cpu: &mut Processor, x: u8) {
+fn inst_Fx33(cpu: &mut Processor, x: u8) -> Result<(), ExecutionError> {
+    let x = x as usize;
+    let address = memory_range(cpu, cpu.i as usize, 3)?.start;
+    cpu.memory[address] = cpu.v[x] / 100;
+    cpu.memory[address + 1] = cpu.v[x] / 10 % 10;
+    cpu.memory[address + 2] = cpu.v[x] % 10;
+    Ok(())
+}
+
+/// Fx3A - PITCH Vx
+/// Set the audio pitch register = Vx.
+/// XO-CHIP only. The audio pattern buffer is played at 4000*2^((Vx-64)/48) bits per second.
+fn inst_Fx3A(cpu: &mut Processor, x: u8) {
     let x = x as usize;
-    let address = cpu.i as usize;
-    cpu.memory[address] = cpu.v[x] & 100;
-    cpu.memory[address + 1] = cpu.v[x] & 10;
-    cpu.memory[address + 2] = cpu.v[x] & 1;
+    cpu.pitch = cpu.v[x];
 }
 
 /// Fx55 - LD [I], Vx
 /// Store registers V0 through Vx in memory starting at location I.
 /// The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
-fn inst_Fx55(cpu: &mut Processor, x: u8) {
+/// Depending on the index_increment quirk I is then advanced past the stored registers.
+fn inst_Fx55(cpu: &mut Processor, x: u8) -> Result<(), ExecutionError> {
     let x = x as usize;
-    let address = cpu.i as usize;
-    for i in 0..=x {
-        cpu.memory[address + i] = cpu.v[i];
-    }
+    let range = memory_range(cpu, cpu.i as usize, x + 1)?;
+    cpu.memory[range].copy_from_slice(&cpu.v[..=x]);
+    increment_index(cpu, x);
+    Ok(())
 }
 
 /// Fx65 - LD Vx, [I]
 /// Read registers V0 through Vx from memory starting at location I.
 /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
-fn inst_Fx65(cpu: &mut Processor, x: u8) {
+/// Depending on the index_increment quirk I is then advanced past the loaded registers.
+fn inst_Fx65(cpu: &mut Processor, x: u8) -> Result<(), ExecutionError> {
+    let x = x as usize;
+    let range = memory_range(cpu, cpu.i as usize, x + 1)?;
+    cpu.v[..=x].copy_from_slice(&cpu.memory[range]);
+    increment_index(cpu, x);
+    Ok(())
+}
+
+/// Advances I after Fx55/Fx65 according to the index_increment quirk.
+fn increment_index(cpu: &mut Processor, x: usize) {
+    cpu.i = cpu.i.wrapping_add(match cpu.quirks.index_increment {
+        IndexIncrement::Unchanged => 0,
+        IndexIncrement::ByX => x as u16,
+        IndexIncrement::ByXPlusOne => x as u16 + 1,
+    });
+}
+
+/// Fx75 - LD R, Vx
+/// Store V0 through Vx in the RPL user flags.
+/// SUPER-CHIP only. The flags survive after the program exits.
+fn inst_Fx75(cpu: &mut Processor, x: u8) {
     let x = x as usize;
-    let address = cpu.i as usize;
-    for i in 0..=x {
-        cpu.v[i] = cpu.memory[address + i];
+    cpu.rpl[..=x].copy_from_slice(&cpu.v[..=x]);
+}
+
+/// Fx85 - LD Vx, R
+/// Read V0 through Vx from the RPL user flags.
+/// SUPER-CHIP only.
+fn inst_Fx85(cpu: &mut Processor, x: u8) {
+    let x = x as usize;
+    cpu.v[..=x].copy_from_slice(&cpu.rpl[..=x]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::{BigFont, SmallFont};
+
+    /// A processor being set up to run a single instruction, using the modern quirks unless told otherwise.
+    struct Given {
+        cpu: Processor,
+        keys: [bool; 16],
+    }
+
+    fn given() -> Given {
+        Given {
+            cpu: Processor::new(Quirks::MODERN),
+            keys: [false; 16],
+        }
+    }
+
+    fn given_platform(platform: Platform) -> Given {
+        Given {
+            cpu: Processor::for_platform(Quirks::MODERN, platform),
+            keys: [false; 16],
+        }
+    }
+
+    impl Given {
+        fn quirks(mut self, quirks: Quirks) -> Given {
+            self.cpu.quirks = quirks;
+            self
+        }
+
+        fn fonts(mut self, fonts: Fonts) -> Given {
+            self.cpu.set_fonts(fonts);
+            self
+        }
+
+        fn v(mut self, x: usize, value: u8) -> Given {
+            self.cpu.v[x] = value;
+            self
+        }
+
+        fn vf(self, value: u8) -> Given {
+            self.v(0xF, value)
+        }
+
+        fn i(mut self, addr: u16) -> Given {
+            self.cpu.i = addr;
+            self
+        }
+
+        fn pc(mut self, addr: u16) -> Given {
+            self.cpu.pc = addr;
+            self
+        }
+
+        fn memory(mut self, addr: usize, bytes: &[u8]) -> Given {
+            self.cpu.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+            self
+        }
+
+        fn stack(mut self, addrs: &[u16]) -> Given {
+            self.cpu.stack[..addrs.len()].copy_from_slice(addrs);
+            self.cpu.sp = addrs.len() as u8;
+            self
+        }
+
+        fn hires(mut self) -> Given {
+            self.cpu.vram = Framebuffer::new(HIRES_WIDTH, HIRES_HEIGHT);
+            self
+        }
+
+        fn pixel(mut self, x: usize, y: usize, value: u8) -> Given {
+            self.cpu.vram.set(x, y, value);
+            self
+        }
+
+        fn planes(mut self, planes: u8) -> Given {
+            self.cpu.planes = planes;
+            self
+        }
+
+        fn delay_timer(mut self, value: u8) -> Given {
+            self.cpu.delay_timer = value;
+            self
+        }
+
+        fn rpl(mut self, flags: [u8; 16]) -> Given {
+            self.cpu.rpl = flags;
+            self
+        }
+
+        fn seed(mut self, seed: u64) -> Given {
+            self.cpu.random = Random::new(seed);
+            self
+        }
+
+        fn key(mut self, key: usize) -> Given {
+            self.keys[key] = true;
+            self
+        }
+
+        /// Executes one instruction placed at the program counter.
+        fn run(mut self, opcode: u16) -> Ran {
+            let pc = self.cpu.pc as usize;
+            self.cpu.memory[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+            let before = self.cpu.vram.clone();
+            let result = self
+                .cpu
+                .cycle(self.keys)
+                .unwrap_or_else(|e| panic!("{:04X}: {}", opcode, e));
+            Ran {
+                cpu: self.cpu,
+                result,
+                before,
+            }
+        }
+
+        /// Executes one instruction that is expected to fail.
+        fn fail(mut self, opcode: u16) -> ExecutionError {
+            let pc = self.cpu.pc as usize;
+            self.cpu.memory[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+            match self.cpu.cycle(self.keys) {
+                Ok(_) => panic!("{:04X} should have failed", opcode),
+                Err(err) => err,
+            }
+        }
+    }
+
+    /// The state after running an instruction, with assertions that can be chained.
+    struct Ran {
+        cpu: Processor,
+        result: CycleResult,
+        before: Framebuffer, // The display before the instruction ran.
+    }
+
+    impl Ran {
+        fn v(&self, x: usize, expected: u8) -> &Ran {
+            assert_eq!(self.cpu.v[x], expected, "V{:X}", x);
+            self
+        }
+
+        fn vf(&self, expected: u8) -> &Ran {
+            self.v(0xF, expected)
+        }
+
+        fn i(&self, expected: u16) -> &Ran {
+            assert_eq!(self.cpu.i, expected, "I");
+            self
+        }
+
+        fn pc(&self, expected: u16) -> &Ran {
+            assert_eq!(self.cpu.pc, expected, "PC");
+            self
+        }
+
+        fn sp(&self, expected: u8) -> &Ran {
+            assert_eq!(self.cpu.sp, expected, "SP");
+            self
+        }
+
+        fn memory(&self, addr: usize, expected: &[u8]) -> &Ran {
+            assert_eq!(
+                &self.cpu.memory[addr..addr + expected.len()],
+                expected,
+                "memory at {:#x}",
+                addr
+            );
+            self
+        }
+
+        /// Checks the display holds exactly the given non-zero pixels.
+        fn pixels(&self, expected: &[(usize, usize, u8)]) -> &Ran {
+            let mut lit = Vec::new();
+            for y in 0..self.cpu.vram.height() {
+                for x in 0..self.cpu.vram.width() {
+                    let value = self.cpu.vram.get(x, y);
+                    if value != 0 {
+                        lit.push((x, y, value));
+                    }
+                }
+            }
+            assert_eq!(lit, expected, "lit pixels");
+            self
+        }
+
+        fn resolution(&self, width: usize, height: usize) -> &Ran {
+            assert_eq!(
+                (self.cpu.vram.width(), self.cpu.vram.height()),
+                (width, height),
+                "resolution"
+            );
+            self
+        }
+
+        fn video_changed(&self, expected: bool) -> &Ran {
+            assert_eq!(self.result.video_changed, expected, "video changed");
+            assert_eq!(
+                self.before != self.cpu.vram,
+                expected,
+                "display contents changed"
+            );
+            self
+        }
+
+        fn exited(&self, expected: bool) -> &Ran {
+            assert_eq!(self.result.exited, expected, "exited");
+            self
+        }
+    }
+
+    #[test]
+    fn inst_0nnn_is_ignored() {
+        given()
+            .v(0, 7)
+            .run(0x0123)
+            .pc(0x202)
+            .v(0, 7)
+            .video_changed(false)
+            .exited(false);
+    }
+
+    #[test]
+    fn inst_00Cn_scrolls_down() {
+        given()
+            .pixel(3, 0, 1)
+            .pixel(3, 31, 1)
+            .run(0x00C2)
+            .pixels(&[(3, 2, 1)])
+            .video_changed(true);
+    }
+
+    #[test]
+    fn inst_00Dn_scrolls_up() {
+        given()
+            .pixel(3, 0, 1)
+            .pixel(3, 5, 1)
+            .run(0x00D3)
+            .pixels(&[(3, 2, 1)]);
+    }
+
+    #[test]
+    fn inst_00E0_clears_selected_planes() {
+        given()
+            .pixel(0, 0, 1)
+            .pixel(63, 31, 2)
+            .run(0x00E0)
+            .pixels(&[(63, 31, 2)])
+            .video_changed(true);
+        given()
+            .planes(0x2)
+            .pixel(0, 0, 1)
+            .pixel(63, 31, 3)
+            .run(0x00E0)
+            .pixels(&[(0, 0, 1), (63, 31, 1)]);
+    }
+
+    #[test]
+    fn inst_00EE_returns() {
+        given().stack(&[0x300, 0x456]).run(0x00EE).pc(0x456).sp(1);
+    }
+
+    #[test]
+    fn inst_00EE_with_an_empty_stack_fails() {
+        assert_eq!(
+            given().fail(0x00EE),
+            ExecutionError::StackUnderflow { pc: 0x200 }
+        );
+    }
+
+    #[test]
+    fn inst_00FB_scrolls_right() {
+        given()
+            .pixel(0, 1, 1)
+            .pixel(62, 1, 1)
+            .run(0x00FB)
+            .pixels(&[(4, 1, 1)]);
+    }
+
+    #[test]
+    fn inst_00FC_scrolls_left() {
+        given()
+            .pixel(1, 1, 1)
+            .pixel(63, 1, 1)
+            .run(0x00FC)
+            .pixels(&[(59, 1, 1)]);
+    }
+
+    #[test]
+    fn inst_00FD_exits() {
+        given().run(0x00FD).exited(true).pc(0x202);
+    }
+
+    #[test]
+    fn inst_00FE_and_00FF_switch_resolution_and_clear() {
+        given()
+            .pixel(1, 1, 1)
+            .run(0x00FF)
+            .resolution(128, 64)
+            .pixels(&[])
+            .video_changed(true);
+        given()
+            .hires()
+            .pixel(1, 1, 1)
+            .run(0x00FE)
+            .resolution(64, 32)
+            .pixels(&[]);
+    }
+
+    #[test]
+    fn inst_1nnn_jumps() {
+        given().run(0x1ABC).pc(0xABC).sp(0);
+    }
+
+    #[test]
+    fn inst_2nnn_calls() {
+        given().run(0x2ABC).pc(0xABC).sp(1);
+        assert_eq!(
+            given().stack(&[0x300]).run(0x2ABC).cpu.stack[..2],
+            [0x300, 0x202]
+        );
+    }
+
+    #[test]
+    fn inst_2nnn_with_a_full_stack_fails() {
+        let err = given().stack(&[0x300; 16]).fail(0x2ABC);
+        assert_eq!(err, ExecutionError::StackOverflow { pc: 0x200 });
+    }
+
+    #[test]
+    fn inst_2nnn_overflows_at_the_platforms_stack_depth() {
+        let err = given_platform(Platform::COSMAC_VIP)
+            .stack(&[0x300; 12])
+            .fail(0x2ABC);
+        assert_eq!(err, ExecutionError::StackOverflow { pc: 0x200 });
+    }
+
+    #[test]
+    fn inst_3xkk_skips_if_equal() {
+        given().v(3, 0x42).run(0x3342).pc(0x204);
+        given().v(3, 0x41).run(0x3342).pc(0x202);
+    }
+
+    #[test]
+    fn skips_step_over_long_loads() {
+        given()
+            .v(3, 0x42)
+            .memory(0x202, &[0xF0, 0x00, 0x12, 0x34])
+            .run(0x3342)
+            .pc(0x206);
+    }
+
+    #[test]
+    fn inst_4xkk_skips_if_not_equal() {
+        given().v(4, 0x41).run(0x4442).pc(0x204);
+        given().v(4, 0x42).run(0x4442).pc(0x202);
+    }
+
+    #[test]
+    fn inst_5xy0_skips_if_registers_equal() {
+        given().v(1, 9).v(2, 9).run(0x5120).pc(0x204);
+        given().v(1, 9).v(2, 8).run(0x5120).pc(0x202);
+    }
+
+    #[test]
+    fn inst_5xy2_stores_a_register_range() {
+        given()
+            .v(1, 1)
+            .v(2, 2)
+            .v(3, 3)
+            .i(0x400)
+            .run(0x5132)
+            .memory(0x400, &[1, 2, 3])
+            .i(0x400);
+        given()
+            .v(1, 1)
+            .v(2, 2)
+            .v(3, 3)
+            .i(0x400)
+            .run(0x5312)
+            .memory(0x400, &[3, 2, 1]);
+    }
+
+    #[test]
+    fn inst_5xy3_loads_a_register_range() {
+        given()
+            .memory(0x400, &[1, 2, 3])
+            .i(0x400)
+            .run(0x5133)
+            .v(1, 1)
+            .v(2, 2)
+            .v(3, 3)
+            .i(0x400);
+        given()
+            .memory(0x400, &[1, 2, 3])
+            .i(0x400)
+            .run(0x5313)
+            .v(3, 1)
+            .v(2, 2)
+            .v(1, 3);
+    }
+
+    #[test]
+    fn inst_6xkk_loads() {
+        given().run(0x6A5C).v(0xA, 0x5C);
+    }
+
+    #[test]
+    fn inst_7xkk_adds_without_carry() {
+        given().v(1, 0xFF).vf(7).run(0x7102).v(1, 0x01).vf(7);
+    }
+
+    #[test]
+    fn inst_8xy0_copies() {
+        given().v(2, 0x33).run(0x8120).v(1, 0x33).v(2, 0x33);
+    }
+
+    #[test]
+    fn inst_8xy1_to_8xy3_do_logic() {
+        given()
+            .v(1, 0b1100)
+            .v(2, 0b1010)
+            .vf(5)
+            .run(0x8121)
+            .v(1, 0b1110)
+            .vf(5);
+        given()
+            .v(1, 0b1100)
+            .v(2, 0b1010)
+            .vf(5)
+            .run(0x8122)
+            .v(1, 0b1000)
+            .vf(5);
+        given()
+            .v(1, 0b1100)
+            .v(2, 0b1010)
+            .vf(5)
+            .run(0x8123)
+            .v(1, 0b0110)
+            .vf(5);
+    }
+
+    #[test]
+    fn inst_8xy1_to_8xy3_reset_vf_with_the_quirk() {
+        for opcode in [0x8121, 0x8122, 0x8123] {
+            given()
+                .quirks(Quirks::COSMAC_VIP)
+                .v(1, 3)
+                .v(2, 5)
+                .vf(5)
+                .run(opcode)
+                .vf(0);
+        }
+    }
+
+    #[test]
+    fn inst_8xy4_sets_carry_past_255() {
+        given().v(1, 0xFE).v(2, 0x01).run(0x8124).v(1, 0xFF).vf(0);
+        given().v(1, 0xFF).v(2, 0x01).run(0x8124).v(1, 0x00).vf(1);
+        given().v(1, 0xFF).v(2, 0xFF).run(0x8124).v(1, 0xFE).vf(1);
+    }
+
+    #[test]
+    fn inst_8xy4_into_vf_keeps_the_carry() {
+        given().vf(0x10).v(2, 0x20).run(0x8F24).vf(0);
+        given().vf(0xF0).v(2, 0x20).run(0x8F24).vf(1);
+        given().v(1, 0xF0).vf(0x20).run(0x81F4).v(1, 0x10).vf(1);
+    }
+
+    #[test]
+    fn inst_8xy5_sets_not_borrow() {
+        given().v(1, 5).v(2, 3).run(0x8125).v(1, 2).vf(1);
+        given().v(1, 3).v(2, 3).run(0x8125).v(1, 0).vf(1);
+        given().v(1, 3).v(2, 4).run(0x8125).v(1, 0xFF).vf(0);
+    }
+
+    #[test]
+    fn inst_8xy5_into_vf_keeps_the_flag() {
+        given().vf(0x10).v(2, 0x20).run(0x8F25).vf(0);
+        given().vf(0x20).v(2, 0x10).run(0x8F25).vf(1);
+    }
+
+    #[test]
+    fn inst_8xy6_shifts_right() {
+        given().v(1, 0b101).v(2, 0b010).run(0x8126).v(1, 0b10).vf(1);
+        given().v(1, 0b100).run(0x8126).v(1, 0b10).vf(0);
+        given()
+            .quirks(Quirks::COSMAC_VIP)
+            .v(1, 0b100)
+            .v(2, 0b011)
+            .run(0x8126)
+            .v(1, 0b1)
+            .v(2, 0b011)
+            .vf(1);
+        given().vf(0b10).run(0x8FF6).vf(0);
+    }
+
+    #[test]
+    fn inst_8xy7_sets_not_borrow() {
+        given().v(1, 3).v(2, 5).run(0x8127).v(1, 2).vf(1);
+        given().v(1, 3).v(2, 3).run(0x8127).v(1, 0).vf(1);
+        given().v(1, 4).v(2, 3).run(0x8127).v(1, 0xFF).vf(0);
+        given().vf(0x20).v(2, 0x10).run(0x8F27).vf(0);
+    }
+
+    #[test]
+    fn inst_8xyE_shifts_left() {
+        given().v(1, 0x81).run(0x812E).v(1, 0x02).vf(1);
+        given().v(1, 0x41).run(0x812E).v(1, 0x82).vf(0);
+        given()
+            .quirks(Quirks::COSMAC_VIP)
+            .v(1, 0x01)
+            .v(2, 0xC0)
+            .run(0x812E)
+            .v(1, 0x80)
+            .vf(1);
+        given().vf(0x40).run(0x8FFE).vf(0);
+    }
+
+    #[test]
+    fn inst_9xy0_skips_if_registers_differ() {
+        given().v(1, 9).v(2, 8).run(0x9120).pc(0x204);
+        given().v(1, 9).v(2, 9).run(0x9120).pc(0x202);
+    }
+
+    #[test]
+    fn inst_Annn_loads_i() {
+        given().run(0xA123).i(0x123);
+    }
+
+    #[test]
+    fn inst_Bnnn_jumps_with_an_offset() {
+        given().v(0, 0x10).v(3, 0x20).run(0xB300).pc(0x310);
+        given()
+            .quirks(Quirks::SUPER_CHIP)
+            .v(0, 0x10)
+            .v(3, 0x20)
+            .run(0xB300)
+            .pc(0x320);
+    }
+
+    #[test]
+    fn inst_Cxkk_masks_random_numbers() {
+        given().v(1, 0xFF).run(0xC100).v(1, 0);
+        for _ in 0..64 {
+            let ran = given().run(0xC10F);
+            assert_eq!(ran.cpu.v[1] & 0xF0, 0);
+        }
+    }
+
+    #[test]
+    fn inst_Cxkk_repeats_with_the_same_seed() {
+        let first = given().seed(1234).run(0xC1FF).cpu.v[1];
+        given().seed(1234).run(0xC1FF).v(1, first);
+    }
+
+    #[test]
+    fn save_states_keep_the_random_number_generator() {
+        let mut cpu = given().seed(99).cpu;
+        let state = cpu.save_state();
+        let first = cpu.random.next_byte();
+        cpu.random.next_byte();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.random.next_byte(), first);
+    }
+
+    #[test]
+    fn save_states_keep_the_font_address() {
+        let fonts = Fonts {
+            address: 0x0,
+            ..Fonts::default()
+        };
+        let state = given().fonts(fonts).cpu.save_state();
+        let mut cpu = Processor::new(Quirks::MODERN);
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.fonts, fonts);
+    }
+
+    #[test]
+    fn invalid_states_leave_the_processor_untouched() {
+        let state = given().v(1, 0x11).cpu.save_state();
+        let mut cpu = given().v(1, 0x22).cpu;
+        let mut corrupt = state.clone();
+        corrupt[20] ^= 0xFF;
+        assert_eq!(cpu.load_state(&corrupt), Err(StateError::ChecksumMismatch));
+        assert_eq!(
+            cpu.load_state(&state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(cpu.v[1], 0x22);
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.v[1], 0x11);
+    }
+
+    #[test]
+    fn platforms_set_the_memory_layout() {
+        let mut cpu = Processor::for_platform(Quirks::MODERN, Platform::ETI_660);
+        cpu.load_cartridge(&[0x12, 0x34]);
+        assert_eq!(cpu.memory().len(), 0x1000);
+        assert_eq!(cpu.memory()[0x600..0x602], [0x12, 0x34]);
+        assert_eq!(cpu.pc(), 0x600);
+        assert_eq!(cpu.framebuffer().height(), 48);
+
+        let cpu = Processor::for_platform(Quirks::MODERN, Platform::HIRES_VIP);
+        assert_eq!(cpu.pc(), 0x2C0);
+        assert_eq!(
+            (cpu.framebuffer().width(), cpu.framebuffer().height()),
+            (64, 64)
+        );
+    }
+
+    #[test]
+    fn save_states_keep_the_platform() {
+        let state = given_platform(Platform::HIRES_VIP)
+            .stack(&[0x300])
+            .cpu
+            .save_state();
+        let mut cpu = Processor::new(Quirks::MODERN);
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.platform(), Platform::HIRES_VIP);
+        assert_eq!(cpu.memory().len(), 0x1000);
+        assert_eq!(cpu.stack(), [0x300]);
+        assert_eq!(cpu.framebuffer().height(), 64);
+    }
+
+    #[test]
+    fn fetching_past_the_end_of_a_small_memory_fails() {
+        let mut cpu = given_platform(Platform::COSMAC_VIP).pc(0xFFF).cpu;
+        assert_eq!(
+            cpu.cycle([false; 16]).err(),
+            Some(ExecutionError::MemoryOutOfBounds {
+                pc: 0xFFF,
+                addr: 0x1000
+            })
+        );
+    }
+
+    #[test]
+    fn inst_Dxyn_draws_and_detects_collisions() {
+        let sprite = [0b1000_0001, 0b0100_0000];
+        given()
+            .v(1, 10)
+            .v(2, 5)
+            .i(0x400)
+            .memory(0x400, &sprite)
+            .run(0xD122)
+            .pixels(&[(10, 5, 1), (17, 5, 1), (11, 6, 1)])
+            .vf(0)
+            .video_changed(true);
+        given()
+            .v(1, 10)
+            .v(2, 5)
+            .i(0x400)
+            .memory(0x400, &sprite)
+            .pixel(11, 6, 1)
+            .pixel(0, 0, 1)
+            .run(0xD122)
+            .pixels(&[(0, 0, 1), (10, 5, 1), (17, 5, 1)])
+            .vf(1);
+    }
+
+    #[test]
+    fn inst_Dxyn_wraps_or_clips_at_the_right_edge() {
+        let wrap = given().v(1, 63).v(2, 0).i(0x400).memory(0x400, &[0xC0]);
+        wrap.run(0xD121).pixels(&[(0, 0, 1), (63, 0, 1)]);
+        let clip = given()
+            .quirks(Quirks::COSMAC_VIP)
+            .v(1, 63)
+            .v(2, 0)
+            .i(0x400)
+            .memory(0x400, &[0xC0]);
+        clip.run(0xD121).pixels(&[(63, 0, 1)]);
+    }
+
+    #[test]
+    fn inst_Dxyn_wraps_or_clips_at_the_bottom_edge() {
+        let wrap = given()
+            .v(1, 0)
+            .v(2, 31)
+            .i(0x400)
+            .memory(0x400, &[0x80, 0x80]);
+        wrap.run(0xD122).pixels(&[(0, 0, 1), (0, 31, 1)]);
+        let clip = given()
+            .quirks(Quirks::COSMAC_VIP)
+            .v(1, 0)
+            .v(2, 31)
+            .i(0x400)
+            .memory(0x400, &[0x80, 0x80]);
+        clip.run(0xD122).pixels(&[(0, 31, 1)]);
+    }
+
+    #[test]
+    fn inst_Dxyn_wraps_the_starting_position() {
+        given()
+            .quirks(Quirks::COSMAC_VIP)
+            .v(1, 64 + 2)
+            .v(2, 32 + 3)
+            .i(0x400)
+            .memory(0x400, &[0x80])
+            .run(0xD121)
+            .pixels(&[(2, 3, 1)]);
+    }
+
+    #[test]
+    fn inst_Dxy0_draws_16x16_sprites() {
+        let mut sprite = [0x0; 32];
+        sprite[0] = 0x80;
+        sprite[31] = 0x01;
+        given()
+            .hires()
+            .v(1, 100)
+            .v(2, 40)
+            .i(0x400)
+            .memory(0x400, &sprite)
+            .run(0xD120)
+            .pixels(&[(100, 40, 1), (115, 55, 1)]);
+    }
+
+    #[test]
+    fn inst_Dxyn_draws_each_selected_plane() {
+        given()
+            .planes(0x3)
+            .i(0x400)
+            .memory(0x400, &[0xC0, 0x80])
+            .run(0xD001)
+            .pixels(&[(0, 0, 3), (1, 0, 1)]);
+        given()
+            .planes(0x2)
+            .i(0x400)
+            .memory(0x400, &[0x80])
+            .pixel(0, 0, 1)
+            .run(0xD001)
+            .pixels(&[(0, 0, 3)])
+            .vf(0);
+    }
+
+    #[test]
+    fn inst_Dxyn_past_the_end_of_memory_fails() {
+        let err = given().i(0xFFFF).fail(0xD002);
+        assert_eq!(
+            err,
+            ExecutionError::MemoryOutOfBounds {
+                pc: 0x200,
+                addr: 0x10000
+            }
+        );
+    }
+
+    #[test]
+    fn inst_Ex9E_skips_if_pressed() {
+        given().v(1, 0xA).key(0xA).run(0xE19E).pc(0x204);
+        given().v(1, 0xA).key(0xB).run(0xE19E).pc(0x202);
+        given().v(1, 0x1A).key(0xA).run(0xE19E).pc(0x204);
+    }
+
+    #[test]
+    fn inst_ExA1_skips_if_not_pressed() {
+        given().v(1, 0xA).key(0xB).run(0xE1A1).pc(0x204);
+        given().v(1, 0xA).key(0xA).run(0xE1A1).pc(0x202);
+    }
+
+    #[test]
+    fn inst_F000_loads_a_long_address() {
+        given()
+            .memory(0x202, &[0xBE, 0xEF])
+            .run(0xF000)
+            .i(0xBEEF)
+            .pc(0x204);
+    }
+
+    #[test]
+    fn inst_Fn01_selects_planes() {
+        assert_eq!(given().run(0xF201).cpu.planes, 0x2);
+        assert_eq!(given().run(0xF301).cpu.planes, 0x3);
+    }
+
+    #[test]
+    fn inst_F002_loads_the_audio_pattern() {
+        let pattern = [0xAA; 16];
+        let ran = given().i(0x400).memory(0x400, &pattern).run(0xF002);
+        assert_eq!(ran.cpu.audio_pattern, Some(pattern));
+    }
+
+    #[test]
+    fn inst_Fx07_reads_the_delay_timer() {
+        given().delay_timer(42).run(0xF307).v(3, 42);
+    }
+
+    #[test]
+    fn inst_Fx0A_waits_for_a_key() {
+        given().v(3, 0xFF).run(0xF30A).pc(0x200).v(3, 0xFF);
+        given().key(0x7).run(0xF30A).pc(0x202).v(3, 0x7);
+    }
+
+    #[test]
+    fn inst_Fx15_and_Fx18_set_the_timers() {
+        assert_eq!(given().v(3, 42).run(0xF315).cpu.delay_timer, 42);
+        assert_eq!(given().v(3, 42).run(0xF318).cpu.sound_timer, 42);
+    }
+
+    #[test]
+    fn inst_Fx1E_adds_to_i() {
+        given().i(0x123).v(3, 0x10).run(0xF31E).i(0x133).vf(0);
+        given().i(0xFFFF).v(3, 0x2).run(0xF31E).i(0x1);
+    }
+
+    #[test]
+    fn inst_Fx29_points_at_a_digit() {
+        given().v(3, 0xA).run(0xF329).i(0x50 + 0xA * 5);
+        given()
+            .v(3, 0x14)
+            .run(0xF329)
+            .i(0x50 + 0x4 * 5)
+            .memory(0x50 + 0x4 * 5, &[0x90, 0x90, 0xF0, 0x10, 0x10]);
+    }
+
+    #[test]
+    fn inst_Fx29_uses_the_chosen_font() {
+        let fonts = Fonts {
+            small: SmallFont::CosmacVip,
+            big: BigFont::SuperChip,
+            address: 0x100,
+        };
+        given()
+            .fonts(fonts)
+            .v(3, 0x4)
+            .run(0xF329)
+            .i(0x100 + 0x4 * 5)
+            .memory(0x100 + 0x4 * 5, &[0xA0, 0xA0, 0xF0, 0x20, 0x20]);
+        given()
+            .fonts(fonts)
+            .v(3, 0x1)
+            .run(0xF330)
+            .i(0x150 + 10)
+            .memory(0x150 + 10, &[0x18, 0x38, 0x58]);
+    }
+
+    #[test]
+    fn inst_Fx30_points_at_a_big_digit() {
+        given()
+            .v(3, 0x2)
+            .run(0xF330)
+            .i(0xA0 + 20)
+            .memory(0xA0 + 20, &[0xFF, 0xFF, 0x03, 0x03]);
+    }
+
+    #[test]
+    fn inst_Fx33_stores_bcd() {
+        for (value, digits) in [
+            (0, [0, 0, 0]),
+            (9, [0, 0, 9]),
+            (10, [0, 1, 0]),
+            (99, [0, 9, 9]),
+            (100, [1, 0, 0]),
+            (255, [2, 5, 5]),
+        ] {
+            given()
+                .v(3, value)
+                .i(0x400)
+                .run(0xF333)
+                .memory(0x400, &digits)
+                .i(0x400);
+        }
+    }
+
+    #[test]
+    fn inst_Fx33_past_the_end_of_memory_fails() {
+        let err = given().i(0xFFFE).fail(0xF333);
+        assert_eq!(
+            err,
+            ExecutionError::MemoryOutOfBounds {
+                pc: 0x200,
+                addr: 0x10000
+            }
+        );
+    }
+
+    #[test]
+    fn inst_Fx3A_sets_the_pitch() {
+        assert_eq!(given().v(3, 100).run(0xF33A).cpu.pitch, 100);
+    }
+
+    #[test]
+    fn inst_Fx55_stores_registers() {
+        given()
+            .v(0, 1)
+            .v(1, 2)
+            .v(2, 3)
+            .v(3, 4)
+            .i(0x400)
+            .run(0xF255)
+            .memory(0x400, &[1, 2, 3, 0])
+            .i(0x400);
+        given()
+            .quirks(Quirks::CHIP_48)
+            .i(0x400)
+            .run(0xF255)
+            .i(0x402);
+        given()
+            .quirks(Quirks::COSMAC_VIP)
+            .i(0x400)
+            .run(0xF255)
+            .i(0x403);
+    }
+
+    #[test]
+    fn inst_Fx65_loads_registers() {
+        given()
+            .memory(0x400, &[1, 2, 3, 4])
+            .i(0x400)
+            .run(0xF265)
+            .v(0, 1)
+            .v(1, 2)
+            .v(2, 3)
+            .v(3, 0)
+            .i(0x400);
+        given()
+            .quirks(Quirks::CHIP_48)
+            .i(0x400)
+            .run(0xF265)
+            .i(0x402);
+        given()
+            .quirks(Quirks::COSMAC_VIP)
+            .i(0x400)
+            .run(0xF265)
+            .i(0x403);
+    }
+
+    #[test]
+    fn inst_Fx65_past_the_end_of_memory_fails() {
+        let err = given().i(0xFFFF).fail(0xF165);
+        assert_eq!(
+            err,
+            ExecutionError::MemoryOutOfBounds {
+                pc: 0x200,
+                addr: 0x10000
+            }
+        );
+    }
+
+    #[test]
+    fn inst_Fx75_and_Fx85_use_the_rpl_flags() {
+        let ran = given().v(0, 1).v(1, 2).v(2, 3).run(0xF175);
+        assert_eq!(ran.cpu.rpl[..3], [1, 2, 0]);
+        let mut flags = [0x0; 16];
+        flags[..3].copy_from_slice(&[4, 5, 6]);
+        given().rpl(flags).run(0xF185).v(0, 4).v(1, 5).v(2, 0);
+    }
+
+    #[test]
+    fn unknown_opcodes_fail() {
+        for opcode in [0x5121, 0x8128, 0xE1FF, 0xF1FF, 0xF100] {
+            assert_eq!(
+                given().fail(opcode),
+                ExecutionError::InvalidOpcode { pc: 0x200, opcode }
+            );
+        }
+    }
+
+    #[test]
+    fn fetching_past_the_end_of_memory_fails() {
+        let mut cpu = given().pc(0xFFFF).cpu;
+        assert_eq!(
+            cpu.cycle([false; 16]).err(),
+            Some(ExecutionError::MemoryOutOfBounds {
+                pc: 0xFFFF,
+                addr: 0x10000
+            })
+        );
     }
 }
@@ -0,0 +1,86 @@
+use crate::error::StateError;
+use crate::state::{StateReader, StateWriter};
+
+/// The random number generator behind Cxkk. Its whole state is a single number, so it can be saved and restored
+/// with the rest of the machine, and the same seed always produces the same bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Random {
+    state: u64, // xorshift64* state.
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // xorshift gets stuck at zero, so mix the seed with a constant that makes zero unreachable from small seeds.
+        let state = match seed ^ 0x9E37_79B9_7F4A_7C15 {
+            0 => 0x9E37_79B9_7F4A_7C15,
+            state => state,
+        };
+        Random { state }
+    }
+
+    /// Writes the generator in the form read by read_from, so it carries on where it left off.
+    pub fn write_to(&self, state: &mut StateWriter) {
+        state.put_u64(self.state);
+    }
+
+    pub fn read_from(state: &mut StateReader) -> Result<Random, StateError> {
+        match state.get_u64()? {
+            0 => Err(StateError::Invalid("random number generator")),
+            state => Ok(Random { state }),
+        }
+    }
+
+    /// The next random byte, from xorshift64*.
+    pub fn next_byte(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(mut random: Random, count: usize) -> Vec<u8> {
+        (0..count).map(|_| random.next_byte()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_repeats() {
+        let first = bytes(Random::new(42), 64);
+        assert_eq!(first, bytes(Random::new(42), 64));
+        assert_ne!(first, bytes(Random::new(43), 64));
+    }
+
+    #[test]
+    fn restoring_the_state_continues_the_sequence() {
+        let mut random = Random::new(7);
+        random.next_byte();
+        let mut state = StateWriter::new();
+        random.write_to(&mut state);
+        let state = state.finish();
+        let restored = Random::read_from(&mut StateReader::new(&state).unwrap()).unwrap();
+        assert_eq!(bytes(random, 16), bytes(restored, 16));
+    }
+
+    #[test]
+    fn stuck_states_are_invalid() {
+        let mut state = StateWriter::new();
+        state.put_u64(0);
+        let state = state.finish();
+        assert_eq!(
+            Random::read_from(&mut StateReader::new(&state).unwrap()),
+            Err(StateError::Invalid("random number generator"))
+        );
+    }
+
+    #[test]
+    fn a_zero_seed_does_not_get_stuck() {
+        let sequence = bytes(Random::new(0), 16);
+        assert!(sequence.iter().any(|&byte| byte != sequence[0]));
+    }
+}
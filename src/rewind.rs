@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+
+/// How a past state is stored relative to the state recorded after it.
+enum Snapshot {
+    Delta(Vec<u8>), // Run length encoded XOR against the next state.
+    Full(Vec<u8>),  // Used when the states differ in size, e.g. after a resolution change.
+}
+
+/// A bounded history of save states for stepping backwards one frame at a time.
+/// Only the newest state is kept in full. Older ones are stored as deltas, since little of memory or the
+/// framebuffer changes from one frame to the next.
+pub struct Rewind {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    history: VecDeque<Snapshot>, // Oldest first.
+}
+
+impl Rewind {
+    /// Keeps up to capacity states.
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            latest: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// The number of states that can be stepped back through.
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Records the newest state, dropping the oldest when full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let snapshot = if previous.len() == state.len() {
+                Snapshot::Delta(encode_delta(&previous, &state))
+            } else {
+                Snapshot::Full(previous)
+            };
+            self.history.push_back(snapshot);
+            while self.history.len() >= self.capacity.max(1) {
+                self.history.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    /// Steps back one state, returning it. It becomes the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let snapshot = self.history.pop_back()?;
+        let latest = self.latest.as_ref()?;
+        let previous = match snapshot {
+            Snapshot::Delta(delta) => apply_delta(latest, &delta),
+            Snapshot::Full(state) => state,
+        };
+        self.latest = Some(previous.clone());
+        Some(previous)
+    }
+}
+
+/// Encodes the XOR of two equal length states as alternating runs: a count of unchanged bytes followed by a count
+/// of changed bytes and their XORed values. Counts are LEB128 varints.
+fn encode_delta(previous: &[u8], next: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut position = 0;
+    while position < previous.len() {
+        let unchanged = unchanged_len(&previous[position..], &next[position..]);
+        position += unchanged;
+        let changed = previous[position..]
+            .iter()
+            .zip(&next[position..])
+            .take_while(|(a, b)| a != b)
+            .count();
+        put_varint(&mut delta, unchanged);
+        put_varint(&mut delta, changed);
+        for offset in position..position + changed {
+            delta.push(previous[offset] ^ next[offset]);
+        }
+        position += changed;
+    }
+    delta
+}
+
+/// The length of the run of equal bytes at the start of two slices. Most of a state is unchanged from one frame to
+/// the next, so it is compared a block at a time first.
+fn unchanged_len(previous: &[u8], next: &[u8]) -> usize {
+    const BLOCK: usize = 64;
+    let mut len = 0;
+    while len + BLOCK <= previous.len() && previous[len..len + BLOCK] == next[len..len + BLOCK] {
+        len += BLOCK;
+    }
+    len + previous[len..]
+        .iter()
+        .zip(&next[len..])
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Recovers the earlier state from the later one and their delta.
+fn apply_delta(next: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut previous = next.to_vec();
+    let mut position = 0;
+    let mut cursor = 0;
+    while cursor < delta.len() {
+        position += get_varint(delta, &mut cursor);
+        let changed = get_varint(delta, &mut cursor);
+        for byte in previous[position..position + changed].iter_mut() {
+            *byte ^= delta[cursor];
+            cursor += 1;
+        }
+        position += changed;
+    }
+    previous
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn get_varint(data: &[u8], cursor: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*cursor];
+        *cursor += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::Processor;
+    use crate::quirks::Quirks;
+
+    /// A state of the given length, differing from its neighbours in a few bytes.
+    fn state(frame: u8, len: usize) -> Vec<u8> {
+        let mut state = vec![0x0; len];
+        state[0] = frame;
+        state[len / 2] = frame.wrapping_mul(3);
+        state
+    }
+
+    #[test]
+    fn states_come_back_newest_first() {
+        let mut rewind = Rewind::new(10);
+        for frame in 0..5 {
+            rewind.push(state(frame, 64));
+        }
+        assert_eq!(rewind.len(), 4);
+        for frame in (0..4).rev() {
+            assert_eq!(rewind.pop(), Some(state(frame, 64)));
+        }
+        assert_eq!(rewind.pop(), None);
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn the_oldest_states_are_dropped_when_full() {
+        let mut rewind = Rewind::new(3);
+        for frame in 0..10 {
+            rewind.push(state(frame, 64));
+        }
+        // The newest state is the current one, so two more can be stepped back to.
+        assert_eq!(rewind.len(), 2);
+        assert_eq!(rewind.pop(), Some(state(8, 64)));
+        assert_eq!(rewind.pop(), Some(state(7, 64)));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn only_the_newest_state_is_kept_in_full() {
+        let mut rewind = Rewind::new(600);
+        for frame in 0..600 {
+            rewind.push(state(frame as u8, 0x10000));
+        }
+        let stored: usize = rewind
+            .history
+            .iter()
+            .map(|snapshot| match snapshot {
+                Snapshot::Delta(delta) => delta.len(),
+                Snapshot::Full(state) => state.len(),
+            })
+            .sum();
+        // Two changed bytes cost a handful of bytes a frame, not another 64 KB.
+        assert!(stored < 600 * 16, "{} bytes stored", stored);
+    }
+
+    #[test]
+    fn states_can_change_size() {
+        let mut rewind = Rewind::new(10);
+        rewind.push(state(1, 64));
+        rewind.push(state(2, 128));
+        rewind.push(state(3, 64));
+        assert_eq!(rewind.pop(), Some(state(2, 128)));
+        assert_eq!(rewind.pop(), Some(state(1, 64)));
+    }
+
+    #[test]
+    fn recording_continues_from_a_rewound_state() {
+        let mut rewind = Rewind::new(10);
+        for frame in 0..4 {
+            rewind.push(state(frame, 64));
+        }
+        assert_eq!(rewind.pop(), Some(state(2, 64)));
+        rewind.push(state(20, 64));
+        rewind.push(state(21, 64));
+        assert_eq!(rewind.pop(), Some(state(20, 64)));
+        assert_eq!(rewind.pop(), Some(state(2, 64)));
+        assert_eq!(rewind.pop(), Some(state(1, 64)));
+    }
+
+    #[test]
+    fn rewinding_restores_the_processor() {
+        let mut cpu = Processor::new(Quirks::MODERN);
+        // 7001: add 1 to V0, 1200: jump back to the start.
+        cpu.load_cartridge(&[0x70, 0x01, 0x12, 0x00]);
+        let mut rewind = Rewind::new(10);
+        rewind.push(cpu.save_state());
+        for _ in 0..3 {
+            for _ in 0..2 {
+                cpu.cycle([false; 16]).unwrap();
+            }
+            rewind.push(cpu.save_state());
+        }
+        assert_eq!(cpu.v()[0], 3);
+        cpu.load_state(&rewind.pop().unwrap()).unwrap();
+        assert_eq!(cpu.v()[0], 2);
+        cpu.load_state(&rewind.pop().unwrap()).unwrap();
+        cpu.load_state(&rewind.pop().unwrap()).unwrap();
+        assert_eq!(cpu.v()[0], 0);
+        assert_eq!(cpu.pc(), 0x200);
+    }
+}
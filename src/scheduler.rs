@@ -0,0 +1,124 @@
+use crate::processor::CycleResult;
+use crate::timing::{Timing, VIP_INTERPRETER_CYCLES};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_CPU_HZ: u32 = 700;
+
+// How far behind the host may fall before the lost frames are dropped rather than caught up.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Paces emulation in 60 Hz frames, each running a share of the instruction rate, or with the COSMAC VIP timing
+/// as many instructions as fit in the machine cycles the VIP's interpreter gets.
+pub struct Scheduler {
+    timing: Timing,
+    instructions_per_frame: f64,
+    owed: f64, // Instructions or machine cycles left in this frame, negative if the last one overran.
+    next_frame: Instant,
+}
+
+impl Scheduler {
+    /// A scheduler for a timing model. The instruction rate is only used by the uniform timing.
+    pub fn new(cpu_hz: u32, timing: Timing) -> Scheduler {
+        Scheduler {
+            timing,
+            instructions_per_frame: cpu_hz as f64 / FRAME_RATE as f64,
+            owed: 0.0,
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn frame_duration() -> Duration {
+        Duration::from_secs(1) / FRAME_RATE
+    }
+
+    /// Gives the coming frame its share of time, along with whatever the last one left over.
+    pub fn start_frame(&mut self) {
+        self.owed += match self.timing {
+            Timing::Uniform => self.instructions_per_frame,
+            Timing::CosmacVip => VIP_INTERPRETER_CYCLES as f64,
+        };
+    }
+
+    /// Whether another instruction starts in this frame.
+    pub fn frame_has_time(&self) -> bool {
+        match self.timing {
+            Timing::Uniform => self.owed >= 1.0,
+            Timing::CosmacVip => self.owed > 0.0,
+        }
+    }
+
+    /// Charges the frame for an executed instruction. An instruction that runs past the end of a frame takes the
+    /// time from the next one.
+    pub fn spend(&mut self, result: &CycleResult) {
+        match self.timing {
+            Timing::Uniform => self.owed -= 1.0,
+            // The VIP idles until the display interrupt, so the sprite is drawn in the next frame.
+            Timing::CosmacVip if result.waits_for_vblank => self.owed = -(result.vip_cycles as f64),
+            Timing::CosmacVip => self.owed -= result.vip_cycles as f64,
+        }
+    }
+
+    /// Sleeps until the next frame is due.
+    pub fn wait_for_next_frame(&mut self) {
+        self.next_frame += Scheduler::frame_duration();
+        let now = Instant::now();
+        if self.next_frame > now {
+            sleep(self.next_frame - now);
+        } else if now - self.next_frame > MAX_LAG {
+            self.next_frame = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn executed(vip_cycles: u32, waits_for_vblank: bool) -> CycleResult {
+        CycleResult {
+            video_changed: waits_for_vblank,
+            exited: false,
+            vip_cycles,
+            waits_for_vblank,
+        }
+    }
+
+    /// Runs a frame of identical instructions, returning how many started.
+    fn run_frame(scheduler: &mut Scheduler, result: &CycleResult) -> u32 {
+        scheduler.start_frame();
+        let mut count = 0;
+        while scheduler.frame_has_time() {
+            scheduler.spend(result);
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn uniform_timing_carries_fractions_over() {
+        let mut scheduler = Scheduler::new(150, Timing::Uniform);
+        let counts: Vec<u32> = (0..4)
+            .map(|_| run_frame(&mut scheduler, &executed(0, false)))
+            .collect();
+        assert_eq!(counts, [2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn vip_timing_fits_instructions_in_the_interpreters_cycles() {
+        let mut scheduler = Scheduler::new(0, Timing::CosmacVip);
+        let count = run_frame(&mut scheduler, &executed(100, false));
+        assert_eq!(count, VIP_INTERPRETER_CYCLES.div_ceil(100));
+    }
+
+    #[test]
+    fn vip_timing_draws_one_sprite_a_frame() {
+        let mut scheduler = Scheduler::new(0, Timing::CosmacVip);
+        assert_eq!(run_frame(&mut scheduler, &executed(300, true)), 1);
+        assert_eq!(run_frame(&mut scheduler, &executed(300, true)), 1);
+        // The drawing left over from the last frame comes out of this one.
+        scheduler.start_frame();
+        assert_eq!(scheduler.owed, (VIP_INTERPRETER_CYCLES - 300) as f64);
+    }
+}
@@ -0,0 +1,11 @@
+//! SDL2 implementations of the frontend traits.
+
+mod audio;
+mod controller;
+mod keyboard;
+mod video;
+
+pub use self::audio::SdlAudio;
+pub use self::controller::Controllers;
+pub use self::keyboard::Keyboard;
+pub use self::video::Video;
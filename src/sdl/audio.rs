@@ -0,0 +1,52 @@
+use crate::audio::{AudioSettings, Sound, ToneGenerator, SAMPLE_RATE};
+use crate::frontend::Audio;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::time::Duration;
+
+struct SdlCallback {
+    generator: ToneGenerator,
+    sound: Sound,
+}
+
+impl AudioCallback for SdlCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.generator.next_sample(&self.sound);
+        }
+    }
+}
+
+/// Plays the sound through the default SDL audio device.
+pub struct SdlAudio {
+    device: AudioDevice<SdlCallback>,
+}
+
+impl SdlAudio {
+    pub fn new(sdl_context: &sdl2::Sdl, settings: AudioSettings) -> Result<SdlAudio, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired, |spec| SdlCallback {
+            generator: ToneGenerator::new(settings, spec.freq as u32),
+            sound: Sound {
+                playing: false,
+                pattern: None,
+                pitch: 64,
+            },
+        })?;
+        device.resume();
+        Ok(SdlAudio { device })
+    }
+}
+
+impl Audio for SdlAudio {
+    // SDL pulls samples as it needs them, so only the sound itself has to be handed over.
+    fn play(&mut self, sound: Sound, _duration: Duration) {
+        self.device.lock().sound = sound;
+    }
+}
@@ -0,0 +1,81 @@
+use crate::keymap::ControllerMap;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+/// Game controllers as another source of CHIP-8 key presses. Controllers are opened as SDL reports them
+/// connected, which includes those plugged in before the emulator started, and dropped when unplugged.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    connected: Vec<GameController>,
+    buttons: Vec<(Button, u8)>,
+    axes: Vec<(Axis, i16, u8)>, // An axis, the sign it must have to press the key, and the key.
+    deadzone: i16,              // How far from the centre an axis must be to press its key.
+}
+
+impl Controllers {
+    /// Fails if the controller map names an input SDL does not know.
+    pub fn new(sdl_context: &sdl2::Sdl, map: &ControllerMap) -> Result<Controllers, String> {
+        let subsystem = sdl_context.game_controller()?;
+        let mut buttons = Vec::new();
+        let mut axes = Vec::new();
+        for (input, key) in map.bindings() {
+            let unknown = || format!("Unknown controller input '{}'.", input);
+            if let Some(axis) = input.strip_suffix('-') {
+                axes.push((Axis::from_string(axis).ok_or_else(unknown)?, -1, *key));
+            } else if let Some(axis) = input.strip_suffix('+') {
+                axes.push((Axis::from_string(axis).ok_or_else(unknown)?, 1, *key));
+            } else if let Some(button) = Button::from_string(input) {
+                buttons.push((button, *key));
+            } else {
+                // The triggers only go one way, from 0 when let go.
+                axes.push((Axis::from_string(input).ok_or_else(unknown)?, 1, *key));
+            }
+        }
+        Ok(Controllers {
+            subsystem,
+            connected: Vec::new(),
+            buttons,
+            axes,
+            deadzone: (map.deadzone * i16::MAX as f32) as i16,
+        })
+    }
+
+    /// Opens and closes controllers as they are plugged in and out.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    println!("Connected {}.", controller.name());
+                    self.connected.push(controller);
+                }
+                Err(err) => eprintln!("Could not open controller {}: {}", which, err),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(i) = self
+                    .connected
+                    .iter()
+                    .position(|controller| controller.instance_id() == which)
+                {
+                    println!("Disconnected {}.", self.connected.remove(i).name());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// The CHIP-8 keys pressed on any connected controller.
+    pub fn keys(&self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for controller in self.connected.iter() {
+            for &(button, key) in self.buttons.iter() {
+                keys[key as usize] |= controller.button(button);
+            }
+            for &(axis, sign, key) in self.axes.iter() {
+                let value = controller.axis(axis) as i32 * sign as i32;
+                keys[key as usize] |= value > self.deadzone as i32;
+            }
+        }
+        keys
+    }
+}
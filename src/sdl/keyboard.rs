@@ -0,0 +1,111 @@
+use super::Controllers;
+use crate::frontend::{Input, KeyboardInput};
+use crate::keymap::{Binding, Hotkey, KeyMap};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use std::collections::HashMap;
+
+pub struct Keyboard {
+    event_pump: sdl2::EventPump,
+    bindings: HashMap<Keycode, Binding>,
+    controllers: Controllers, // Merged with the keyboard as they share the event pump.
+}
+
+impl Keyboard {
+    /// Fails if the key map names a key SDL does not know.
+    pub fn new(
+        sdl_context: &sdl2::Sdl,
+        keymap: &KeyMap,
+        controllers: Controllers,
+    ) -> Result<Keyboard, String> {
+        let event_pump = sdl_context.event_pump().unwrap();
+        let mut bindings = HashMap::new();
+        for (name, binding) in keymap.bindings() {
+            let keycode = Keycode::from_name(name)
+                .ok_or_else(|| format!("Unknown key '{}' bound to {}.", name, binding))?;
+            bindings.insert(keycode, binding);
+        }
+        Ok(Keyboard {
+            event_pump,
+            bindings,
+            controllers,
+        })
+    }
+}
+
+impl Input for Keyboard {
+    fn handle_input(&mut self) -> KeyboardInput {
+        for event in self.event_pump.poll_iter() {
+            self.controllers.handle_event(&event);
+            match event {
+                Event::Quit { .. } => return KeyboardInput::Quit,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    // F1 to F9 load the numbered save slot, with shift they save to it.
+                    if let Some(slot) = save_slot(keycode) {
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            return KeyboardInput::SaveState(slot);
+                        }
+                        return KeyboardInput::LoadState(slot);
+                    }
+                    match self.bindings.get(&keycode) {
+                        Some(Binding::Hotkey(Hotkey::Quit)) => return KeyboardInput::Quit,
+                        Some(Binding::Hotkey(Hotkey::Pause)) => return KeyboardInput::Pause,
+                        Some(Binding::Hotkey(Hotkey::Reset)) => return KeyboardInput::Reset,
+                        Some(Binding::Hotkey(Hotkey::SaveState)) => {
+                            return KeyboardInput::SaveState(0)
+                        }
+                        Some(Binding::Hotkey(Hotkey::LoadState)) => {
+                            return KeyboardInput::LoadState(0)
+                        }
+                        Some(Binding::Hotkey(Hotkey::SpeedUp)) => return KeyboardInput::SpeedUp,
+                        Some(Binding::Hotkey(Hotkey::SpeedDown)) => {
+                            return KeyboardInput::SpeedDown
+                        }
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let keys: Vec<Keycode> = self
+            .event_pump
+            .keyboard_state()
+            .pressed_scancodes()
+            .filter_map(Keycode::from_scancode)
+            .collect();
+
+        let mut key_codes = self.controllers.keys();
+
+        for key in keys {
+            match self.bindings.get(&key) {
+                Some(Binding::Keypad(i)) => key_codes[*i as usize] = true,
+                // Rewinding lasts for as long as the key is held.
+                Some(Binding::Hotkey(Hotkey::Rewind)) => return KeyboardInput::Rewind,
+                _ => (),
+            }
+        }
+
+        KeyboardInput::Input(key_codes)
+    }
+}
+
+fn save_slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
@@ -0,0 +1,55 @@
+use crate::framebuffer::Framebuffer;
+use crate::frontend::Display;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+const WINDOW_WIDTH: u32 = 640;
+const WINDOW_HEIGHT: u32 = 320;
+
+pub struct Video {
+    canvas: sdl2::render::WindowCanvas,
+    palette: [Color; 4], // The colour of each combination of the two bitplanes.
+}
+
+impl Video {
+    pub fn new(sdl_context: &sdl2::Sdl, palette: [(u8, u8, u8); 4]) -> Video {
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = video_subsystem
+            .window("Chip-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
+            .position_centered()
+            .build()
+            .unwrap();
+        let canvas = window.into_canvas().build().unwrap();
+        Video {
+            canvas,
+            palette: palette.map(|(r, g, b)| Color::RGB(r, g, b)),
+        }
+    }
+}
+
+impl Display for Video {
+    fn update(&mut self, frame: &Framebuffer) {
+        // Scale each emulated pixel so the frame fills as much of the window as it can, centred in it.
+        let pixel_size =
+            (WINDOW_WIDTH / frame.width() as u32).min(WINDOW_HEIGHT / frame.height() as u32);
+        let left = (WINDOW_WIDTH - frame.width() as u32 * pixel_size) / 2;
+        let top = (WINDOW_HEIGHT - frame.height() as u32 * pixel_size) / 2;
+        self.canvas.set_draw_color(self.palette[0x0]);
+        self.canvas.clear();
+        for i in 0..frame.height() {
+            for j in 0..frame.width() {
+                let pixel = frame.get(j, i);
+                if pixel != 0x0 {
+                    self.canvas
+                        .set_draw_color(self.palette[pixel as usize & 0x3]);
+                    let x: i32 = (left + j as u32 * pixel_size) as i32;
+                    let y: i32 = (top + i as u32 * pixel_size) as i32;
+                    self.canvas
+                        .fill_rect(Rect::new(x, y, pixel_size, pixel_size))
+                        .expect("Unable to draw rectange.");
+                }
+            }
+        }
+        self.canvas.present();
+    }
+}
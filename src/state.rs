@@ -0,0 +1,293 @@
+use crate::error::StateError;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+pub const STATE_VERSION: u16 = 5;
+const HEADER_LEN: usize = 10; // Magic, version and payload length.
+
+/// Builds a save state: a header, the payload and a CRC-32 of the payload.
+pub struct StateWriter {
+    payload: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter {
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.payload.push(value);
+    }
+
+    pub fn put_bool(&mut self, value: bool) {
+        self.payload.push(value as u8);
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a length prefixed byte string.
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        self.put_u32(bytes.len() as u32);
+        self.payload.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.finish_as(MAGIC, STATE_VERSION)
+    }
+
+    /// Finishes with another file's magic number and version, for formats that share this layout.
+    pub fn finish_as(self, magic: &[u8; 4], version: u16) -> Vec<u8> {
+        let mut state = Vec::with_capacity(HEADER_LEN + self.payload.len() + 4);
+        state.extend_from_slice(magic);
+        state.extend_from_slice(&version.to_le_bytes());
+        state.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        state.extend_from_slice(&self.payload);
+        state.extend_from_slice(&crc32fast::hash(&self.payload).to_le_bytes());
+        state
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+/// Reads back the payload of a save state written by StateWriter.
+pub struct StateReader<'a> {
+    payload: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header and checksum of a save state.
+    pub fn new(state: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        StateReader::open(state, MAGIC, STATE_VERSION)
+    }
+
+    /// Checks the header and checksum of a file written with StateWriter::finish_as.
+    pub fn open(
+        state: &'a [u8],
+        magic: &[u8; 4],
+        version: u16,
+    ) -> Result<StateReader<'a>, StateError> {
+        if state.len() < HEADER_LEN || &state[..4] != magic {
+            return Err(StateError::BadMagic);
+        }
+        let found = u16::from_le_bytes([state[4], state[5]]);
+        if found != version {
+            return Err(StateError::UnsupportedVersion(found));
+        }
+        let len = u32::from_le_bytes([state[6], state[7], state[8], state[9]]) as usize;
+        if state.len() != HEADER_LEN + len + 4 {
+            return Err(StateError::Truncated);
+        }
+        let payload = &state[HEADER_LEN..HEADER_LEN + len];
+        let checksum = &state[HEADER_LEN + len..];
+        if crc32fast::hash(payload).to_le_bytes() != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+        Ok(StateReader {
+            payload,
+            position: 0,
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.position + len > self.payload.len() {
+            return Err(StateError::Truncated);
+        }
+        let bytes = &self.payload[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool, StateError> {
+        match self.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean")),
+        }
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0x0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads a length prefixed byte string.
+    pub fn get_bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.get_u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads a length prefixed byte string that must be exactly N bytes long.
+    pub fn get_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let bytes = self.get_bytes()?;
+        let mut array = [0x0; N];
+        if bytes.len() != N {
+            return Err(StateError::Invalid("array length"));
+        }
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    fn state(payload: &[u8]) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        for &byte in payload {
+            state.put_u8(byte);
+        }
+        state.finish()
+    }
+
+    fn open(state: &[u8]) -> Result<StateReader<'_>, StateError> {
+        StateReader::new(state)
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        let mut state = StateWriter::new();
+        state.put_u8(0x12);
+        state.put_bool(true);
+        state.put_u16(0x3456);
+        state.put_u32(0x789A_BCDE);
+        state.put_u64(u64::MAX - 1);
+        state.put_bytes(&[0x1, 0x2, 0x3]);
+        let data = state.finish();
+        let mut state = open(&data).unwrap();
+        assert_eq!(state.get_u8(), Ok(0x12));
+        assert_eq!(state.get_bool(), Ok(true));
+        assert_eq!(state.get_u16(), Ok(0x3456));
+        assert_eq!(state.get_u32(), Ok(0x789A_BCDE));
+        assert_eq!(state.get_u64(), Ok(u64::MAX - 1));
+        assert_eq!(state.get_array(), Ok([0x1, 0x2, 0x3]));
+        assert_eq!(state.get_u8(), Err(StateError::Truncated));
+    }
+
+    #[test]
+    fn other_files_are_not_states() {
+        let mut data = state(&[0x0]);
+        data[0] = b'X';
+        assert_eq!(open(&data).err(), Some(StateError::BadMagic));
+        assert_eq!(open(b"C8S").err(), Some(StateError::BadMagic));
+        assert_eq!(open(&[]).err(), Some(StateError::BadMagic));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut data = state(&[0x0]);
+        data[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            open(&data).err(),
+            Some(StateError::UnsupportedVersion(STATE_VERSION + 1))
+        );
+        let data = StateWriter::new().finish_as(MAGIC, STATE_VERSION - 1);
+        assert_eq!(
+            open(&data).err(),
+            Some(StateError::UnsupportedVersion(STATE_VERSION - 1))
+        );
+    }
+
+    #[test]
+    fn lengths_must_match_the_data() {
+        let data = state(&[0x1, 0x2, 0x3]);
+        assert_eq!(
+            open(&data[..data.len() - 1]).err(),
+            Some(StateError::Truncated)
+        );
+        let mut longer = data.clone();
+        longer.push(0x0);
+        assert_eq!(open(&longer).err(), Some(StateError::Truncated));
+        let mut overstated = data;
+        overstated[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(open(&overstated).err(), Some(StateError::Truncated));
+    }
+
+    #[test]
+    fn corrupt_payloads_fail_the_checksum() {
+        let mut data = state(&[0x1, 0x2, 0x3]);
+        data[HEADER_LEN + 1] ^= 0x80;
+        assert_eq!(open(&data).err(), Some(StateError::ChecksumMismatch));
+        let mut data = state(&[0x1, 0x2, 0x3]);
+        let last = data.len() - 1;
+        data[last] ^= 0x1;
+        assert_eq!(open(&data).err(), Some(StateError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn byte_strings_cannot_run_past_the_payload() {
+        let mut state = StateWriter::new();
+        state.put_u32(100);
+        state.put_u8(0x0);
+        let data = state.finish();
+        assert_eq!(open(&data).unwrap().get_bytes(), Err(StateError::Truncated));
+    }
+
+    #[test]
+    fn arrays_must_have_their_length() {
+        let mut state = StateWriter::new();
+        state.put_bytes(&[0x0; 15]);
+        let data = state.finish();
+        assert_eq!(
+            open(&data).unwrap().get_array::<16>(),
+            Err(StateError::Invalid("array length"))
+        );
+    }
+
+    #[test]
+    fn booleans_are_zero_or_one() {
+        let data = state(&[0x2]);
+        assert_eq!(
+            open(&data).unwrap().get_bool(),
+            Err(StateError::Invalid("boolean"))
+        );
+    }
+
+    #[test]
+    fn unknown_quirk_settings_are_invalid() {
+        let mut state = StateWriter::new();
+        Quirks::MODERN.write_to(&mut state);
+        let mut data = state.finish();
+        data[HEADER_LEN + 1] = 0x3;
+        // Fix the checksum, so the bad value reaches Quirks::read_from.
+        let payload_end = data.len() - 4;
+        let checksum = crc32fast::hash(&data[HEADER_LEN..payload_end]).to_le_bytes();
+        data[payload_end..].copy_from_slice(&checksum);
+        assert_eq!(
+            Quirks::read_from(&mut open(&data).unwrap()),
+            Err(StateError::Invalid("quirks"))
+        );
+    }
+}
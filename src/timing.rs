@@ -0,0 +1,144 @@
+use crate::error::StateError;
+use crate::state::{StateReader, StateWriter};
+
+/// Machine cycles in a 60 Hz frame: the VIP's 1.7609 MHz clock, at 8 clock cycles per machine cycle.
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// Machine cycles the interpreter gets each frame. The 1861 display chip's DMA takes one for each of the 8 bytes of
+/// its 128 lines, and the interrupt routine that starts it and counts the timers down takes 46 more.
+pub const VIP_INTERPRETER_CYCLES: u32 = VIP_CYCLES_PER_FRAME - 8 * 128 - 46;
+
+/// Machine cycles the interpreter's loop takes to fetch an instruction and jump to its routine.
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// How long instructions take.
+/// The discriminants are stored in movies, so the order must not change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    Uniform,   // Every instruction takes the same time, a share of the instructions per second.
+    CosmacVip, // Instructions take as long as on the COSMAC VIP, and Dxyn waits for the display.
+}
+
+impl Timing {
+    /// Looks up a timing model by name, as given on the command line.
+    pub fn from_name(name: &str) -> Option<Timing> {
+        match name.to_lowercase().as_str() {
+            "uniform" => Some(Timing::Uniform),
+            "vip" | "cosmac-vip" => Some(Timing::CosmacVip),
+            _ => None,
+        }
+    }
+
+    /// Writes the timing model in the form read by read_from.
+    pub fn write_to(&self, state: &mut StateWriter) {
+        state.put_u8(*self as u8);
+    }
+
+    pub fn read_from(state: &mut StateReader) -> Result<Timing, StateError> {
+        match state.get_u8()? {
+            0 => Ok(Timing::Uniform),
+            1 => Ok(Timing::CosmacVip),
+            _ => Err(StateError::Invalid("timing")),
+        }
+    }
+}
+
+/// The machine cycles the COSMAC VIP interpreter takes to execute an instruction, given the registers and keys
+/// before it runs. For Dxyn this is only the drawing, which starts once the display has been waited for.
+/// Instructions the VIP does not have cost just the fetch.
+pub fn vip_cycles(opcode: u16, v: &[u8; 16], keys: [bool; 16]) -> u32 {
+    let x = (opcode >> 8 & 0xf) as usize;
+    let y = (opcode >> 4 & 0xf) as usize;
+    let kk = (opcode & 0xff) as u8;
+    let n = (opcode & 0xf) as u32;
+    // Skipping an instruction costs the two increments of the program counter.
+    let skip = |skipped: bool| if skipped { 4 } else { 0 };
+    let execute = match opcode >> 12 {
+        0x0 => match opcode {
+            // The loop clearing the 256 bytes of display memory takes 12 cycles a byte.
+            0x00E0 => 24 + 12 * 256,
+            0x00EE => 10,
+            _ => 0,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 => 10 + skip(v[x] == kk),
+        0x4 => 10 + skip(v[x] != kk),
+        0x5 if n == 0x0 => 14 + skip(v[x] == v[y]),
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0x9 if n == 0x0 => 14 + skip(v[x] != v[y]),
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        0xD => {
+            // Each row is shifted into place a bit at a time, and a row that straddles two bytes of display memory
+            // is combined with both.
+            let shift = (v[x] & 0x7) as u32;
+            let straddles = if shift != 0 { 16 } else { 0 };
+            26 + n * (34 + 8 * shift + straddles)
+        }
+        0xE => match kk {
+            0x9E => 14 + skip(keys[v[x] as usize & 0xf]),
+            0xA1 => 14 + skip(!keys[v[x] as usize & 0xf]),
+            _ => 0,
+        },
+        0xF => match kk {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 18,
+            0x1E => 16,
+            0x29 => 20,
+            // Each digit is found by repeated subtraction.
+            0x33 => {
+                let value = v[x] as u32;
+                84 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+            }
+            0x55 | 0x65 => 22 + 14 * (x as u32 + 1),
+            _ => 0,
+        },
+        _ => 0,
+    };
+    VIP_FETCH_CYCLES + execute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_take_longer() {
+        let mut v = [0x0; 16];
+        let keys = [false; 16];
+        assert_eq!(
+            vip_cycles(0x3000, &v, keys),
+            vip_cycles(0x3001, &v, keys) + 4
+        );
+        v[1] = 0x5;
+        assert_eq!(
+            vip_cycles(0x5120, &v, keys) + 4,
+            vip_cycles(0x9120, &v, keys)
+        );
+    }
+
+    #[test]
+    fn sprites_cost_more_when_unaligned() {
+        let mut v = [0x0; 16];
+        let keys = [false; 16];
+        let aligned = vip_cycles(0xD015, &v, keys);
+        assert_eq!(aligned, VIP_FETCH_CYCLES + 26 + 5 * 34);
+        v[0] = 3;
+        assert_eq!(vip_cycles(0xD015, &v, keys), aligned + 5 * (8 * 3 + 16));
+        assert!(vip_cycles(0xD01F, &v, keys) > vip_cycles(0xD015, &v, keys));
+    }
+
+    #[test]
+    fn bcd_depends_on_the_digits() {
+        let mut v = [0x0; 16];
+        v[2] = 199;
+        assert_eq!(
+            vip_cycles(0xF233, &v, [false; 16]),
+            VIP_FETCH_CYCLES + 84 + 16 * 19
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Runs test ROMs headlessly and compares their final display with golden images in tests/golden.
+//!
+//! The ROMs in tests/roms are written for this suite in place of the community test ROMs, which cannot be bundled.
+//! They check themselves where they can and between them execute every instruction.
+//!
+//! After an intended change to what a ROM shows, run the tests with UPDATE_GOLDEN=1 set to rewrite the golden
+//! images, then check the new ones by eye before committing them.
+
+use chip_8::headless::{self, HeadlessSettings, KeyScript, StopReason};
+use chip_8::image;
+use chip_8::octo;
+use chip_8::platform::Platform;
+use chip_8::processor::Processor;
+use chip_8::quirks::Quirks;
+use chip_8::timing::Timing;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAX_FRAMES: u32 = 1200;
+
+struct Case {
+    rom: &'static str,    // Relative to tests/roms.
+    golden: &'static str, // Relative to tests/golden.
+    quirks: Quirks,
+    keys: &'static str,
+}
+
+fn path(dir: &str, file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir)
+        .join(file)
+}
+
+/// Reads a ROM, compiling it first if it is Octo source.
+fn load(path: &Path) -> Vec<u8> {
+    if path.extension().is_some_and(|e| e == "8o") {
+        let source = fs::read_to_string(path).unwrap();
+        return octo::compile(&source, Platform::default().load_address)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+            .rom;
+    }
+    fs::read(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn boot(rom: &[u8], quirks: Quirks) -> Processor {
+    let mut cpu = Processor::new(quirks);
+    cpu.load_cartridge(rom);
+    cpu
+}
+
+/// Runs a ROM until it exits or loops forever, then checks what it left on the display.
+fn check(case: &Case) {
+    let mut cpu = boot(&load(&path("roms", case.rom)), case.quirks);
+    let settings = HeadlessSettings {
+        cpu_hz: 1000 * 60,
+        timing: Timing::Uniform,
+        max_frames: Some(MAX_FRAMES),
+        stop_at: None,
+        stop_on_loop: true,
+        keys: KeyScript::parse(case.keys).unwrap(),
+    };
+    let (reason, _) = headless::run(&mut cpu, &settings)
+        .unwrap_or_else(|e| panic!("{}: {}\n{}", case.rom, e, cpu.register_dump()));
+    assert_ne!(
+        reason,
+        StopReason::FrameLimit,
+        "{} never finished",
+        case.rom
+    );
+
+    let actual = image::ascii(cpu.framebuffer());
+    let golden = path("golden", case.golden);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+        panic!(
+            "{} has no golden image, run with UPDATE_GOLDEN=1 to create it. It showed:\n{}",
+            golden.display(),
+            actual
+        )
+    });
+    assert!(
+        actual == expected,
+        "{} does not match {}. It showed:\n{}",
+        case.rom,
+        golden.display(),
+        actual
+    );
+}
+
+#[test]
+fn instructions() {
+    // Fx0A and Ex9E wait for or look at key 5.
+    check(&Case {
+        rom: "instructions.8o",
+        golden: "instructions.txt",
+        quirks: Quirks::MODERN,
+        keys: "0:5",
+    });
+}
+
+#[test]
+fn display() {
+    check(&Case {
+        rom: "display.8o",
+        golden: "display.txt",
+        quirks: Quirks::XO_CHIP,
+        keys: "",
+    });
+}
+
+#[test]
+fn quirk_profiles() {
+    let profiles = [
+        (Quirks::COSMAC_VIP, "quirks-vip.txt"),
+        (Quirks::CHIP_48, "quirks-chip48.txt"),
+        (Quirks::SUPER_CHIP, "quirks-schip.txt"),
+        (Quirks::XO_CHIP, "quirks-xochip.txt"),
+        (Quirks::MODERN, "quirks-modern.txt"),
+    ];
+    for (quirks, golden) in profiles {
+        check(&Case {
+            rom: "quirks.8o",
+            golden,
+            quirks,
+            keys: "",
+        });
+    }
+}
+
+/// The name of the instruction function an opcode runs, following the dispatch in Processor::cycle.
+fn instruction_name(opcode: u16) -> String {
+    let x = (opcode & 0x0f00) >> 8;
+    let n = opcode & 0x000f;
+    let name = match opcode >> 12 {
+        0x0 => match opcode & 0x0fff {
+            0x0C0..=0x0CF => "00Cn",
+            0x0D0..=0x0DF => "00Dn",
+            0x0E0 => "00E0",
+            0x0EE => "00EE",
+            0x0FB => "00FB",
+            0x0FC => "00FC",
+            0x0FD => "00FD",
+            0x0FE => "00FE",
+            0x0FF => "00FF",
+            _ => "0nnn",
+        },
+        0x1 => "1nnn",
+        0x2 => "2nnn",
+        0x3 => "3xkk",
+        0x4 => "4xkk",
+        0x5 => return format!("5xy{:X}", n),
+        0x6 => "6xkk",
+        0x7 => "7xkk",
+        0x8 => return format!("8xy{:X}", n),
+        0x9 => "9xy0",
+        0xA => "Annn",
+        0xB => "Bnnn",
+        0xC => "Cxkk",
+        0xD => "Dxyn",
+        0xE => return format!("Ex{:02X}", opcode & 0xff),
+        _ => match (x, opcode & 0xff) {
+            (0x0, 0x00) => "F000",
+            (_, 0x01) => "Fn01",
+            (0x0, 0x02) => "F002",
+            (_, nn) => return format!("Fx{:02X}", nn),
+        },
+    };
+    name.to_string()
+}
+
+#[test]
+fn every_instruction_runs() {
+    let expected: BTreeSet<String> = [
+        "0nnn", "00Cn", "00Dn", "00E0", "00EE", "00FB", "00FC", "00FD", "00FE", "00FF", "1nnn",
+        "2nnn", "3xkk", "4xkk", "5xy0", "5xy2", "5xy3", "6xkk", "7xkk", "8xy0", "8xy1", "8xy2",
+        "8xy3", "8xy4", "8xy5", "8xy6", "8xy7", "8xyE", "9xy0", "Annn", "Bnnn", "Cxkk", "Dxyn",
+        "Ex9E", "ExA1", "F000", "Fn01", "F002", "Fx07", "Fx0A", "Fx15", "Fx18", "Fx1E", "Fx29",
+        "Fx30", "Fx33", "Fx3A", "Fx55", "Fx65", "Fx75", "Fx85",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+
+    let mut key_5 = [false; 16];
+    key_5[5] = true;
+    let mut seen = BTreeSet::new();
+    for (rom, quirks) in [
+        ("instructions.8o", Quirks::MODERN),
+        ("display.8o", Quirks::XO_CHIP),
+    ] {
+        let mut cpu = boot(&load(&path("roms", rom)), quirks);
+        for step in 0.. {
+            assert!(step < 1_000_000, "{} never finished", rom);
+            let pc = cpu.pc() as usize;
+            let opcode = (cpu.memory()[pc] as u16) << 8 | cpu.memory()[pc + 1] as u16;
+            seen.insert(instruction_name(opcode));
+            if cpu.cycle(key_5).unwrap().exited {
+                break;
+            }
+            if step % 16 == 0 {
+                cpu.tick_timers();
+            }
+        }
+    }
+    let missed: Vec<_> = expected.difference(&seen).collect();
+    assert!(missed.is_empty(), "never executed: {:?}", missed);
+}